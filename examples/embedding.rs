@@ -0,0 +1,49 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use vega::core::error::Error;
+use vega::core::interpreter::Interpreter;
+use vega::core::value::Value;
+
+/// Runs a script from a host application: `cargo run --example embedding`.
+fn main() {
+    let mut interpreter = Interpreter::new();
+
+    // State shared between the host and the closures given to scripts.
+    let calls = Rc::new(Cell::new(0));
+    let log = Rc::new(RefCell::new(Vec::new()));
+
+    let counter = calls.clone();
+    interpreter.register_fn("tick", move |_| {
+        counter.set(counter.get() + 1);
+        Ok(Value::Number(counter.get() as f32))
+    });
+    let lines = log.clone();
+    interpreter.register_fn("log", move |args| match args {
+        [Value::String(line)] => {
+            lines.borrow_mut().push(line.clone());
+            Ok(Value::Nil)
+        },
+        _ => Err(Error::type_error("log expects a string")),
+    });
+    interpreter.set_global("greeting", Value::String("hello".to_string()));
+
+    let source = r#"
+        (fn greet (name) (do (tick) (log (concat greeting " " name)) (tick)))
+        (greet "world")
+    "#;
+    match interpreter.eval_str(source) {
+        Ok(value) => println!("script returned {}", value),
+        Err(signal) => eprintln!("script failed: {:?}", signal),
+    }
+    match interpreter.call_function("greet", vec![Value::String("host".to_string())]) {
+        Ok(value) => println!("greet returned {}", value),
+        Err(signal) => eprintln!("greet failed: {:?}", signal),
+    }
+    if let Err(signal) = interpreter.eval_str("(log 42)") {
+        eprintln!("log failed: {:?}", signal);
+    }
+
+    println!("tick called {} times", calls.get());
+    println!("log: {:?}", log.borrow());
+    println!("greet is {}", interpreter.get_global("greet").unwrap_or(Value::Nil));
+}
@@ -1,7 +1,20 @@
+use std::fmt;
+use std::rc::Rc;
 use crate::core::expr::Expr;
 
 #[derive(Debug, Clone)]
 pub enum Annotation {
     Require(Expr),
-    Test {args: Vec<Expr>, expected: Expr}
-}
\ No newline at end of file
+    Test {args: Rc<[Expr]>, expected: Expr}
+}
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Annotation::Require(requirement) => write!(f, "(:require {})", requirement),
+            Annotation::Test { args, expected } => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "(:test ({}) {})", args.join(" "), expected)
+            },
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::core::value::Value;
+
+#[derive(Debug)]
+pub struct Field {
+    pub name: String,
+    pub default: Option<Value>,
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub fields: Vec<Field>,
+    pub methods: RefCell<HashMap<String, Value>>,
+}
+
+impl Class {
+    pub fn new(name: &str, fields: Vec<Field>) -> Self {
+        Class {
+            name: name.to_string(),
+            fields,
+            methods: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn method(&self, name: &str) -> Option<Value> {
+        self.methods.borrow().get(name).cloned()
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
@@ -0,0 +1,385 @@
+use crate::core::expr::{Expr, Span};
+use crate::core::interpreter::Interpreter;
+use crate::core::params::Params;
+use crate::core::resolver::{Binding, Resolver};
+use crate::core::symbol::{self, Symbol};
+use crate::core::value::Value;
+
+/// Instruction of the stack machine run by `Interpreter::run_chunk`. Operands
+/// index the tables of the `Chunk`, jump targets index `Chunk::code`.
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    Constant(usize),
+    /// Pushes a variable, or nil when it is not defined. Reading through the
+    /// binding falls back to a lookup by name when the scopes do not have the
+    /// shape the resolver expected.
+    Get { name: usize, binding: Binding },
+    /// `var`: binds the top of the stack in the current scope, keeping it on the stack.
+    Define(usize),
+    /// `let`: pops one value per name of the list and binds them in the current scope.
+    Bind(usize),
+    /// Runs `form` with the tree walker and jumps to `skip` unless `name` holds a
+    /// plain value that `Set` can replace.
+    SetGuard { name: usize, binding: Binding, form: usize, skip: usize },
+    Set { name: usize, binding: Binding },
+    Pop,
+    /// Drops the value below the top of the stack.
+    Nip,
+    Jump(usize),
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    /// Short-circuit jumps of `and`/`or`, the value stays when jumping.
+    JumpIfFalseOrPop(usize),
+    JumpIfTrueOrPop(usize),
+    Array(usize),
+    /// Pushes the function bound to `name`. Macros, classes and other call
+    /// targets run `form` with the tree walker instead and jump to `skip`.
+    Callee { name: usize, binding: Binding, form: usize, skip: usize },
+    Call(usize),
+    /// Fallback for the forms that have no bytecode, run by the tree walker.
+    Eval(usize),
+    PushScope,
+    PopScope,
+    /// Registers the innermost loop, `break` jumps to `exit` and `continue` to `next`.
+    /// A `while` keeps its last body value on the stack and runs its body in a scope
+    /// shared by every iteration.
+    LoopStart { exit: usize, next: usize, keep_last: bool },
+    /// Enters the body scope of the innermost `while`.
+    LoopScope,
+    LoopEnd,
+    /// Pops `count` ranges and starts iterating them with the parameter names list.
+    ForInit { names: usize, count: usize },
+    /// Binds the next values of the innermost `for`, or jumps when a range is exhausted.
+    ForNext(usize),
+    ForEnd,
+    Break,
+    Continue,
+    Return,
+}
+
+/// Compiled form: its code and the tables the instructions refer to.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    /// Innermost list form of each instruction, given to errors raised by it.
+    pub spans: Vec<Option<Span>>,
+    pub constants: Vec<Value>,
+    pub names: Vec<Symbol>,
+    pub name_lists: Vec<Vec<Symbol>>,
+    pub forms: Vec<Expr>,
+}
+
+/// Compiles forms to bytecode. Forms the compiler does not know, or that are
+/// malformed, become an `Op::Eval` so that both engines behave the same.
+pub struct Compiler {
+    chunk: Chunk,
+    span: Option<Span>,
+    in_function: bool,
+    loops: usize,
+    resolver: Resolver,
+}
+
+impl Compiler {
+    /// Compiles a program, its value is the value of the last form.
+    pub fn program(exprs: &[Expr]) -> Chunk {
+        let mut compiler = Compiler { chunk: Chunk::default(), span: None, in_function: false, loops: 0, resolver: Resolver::default() };
+        compiler.sequence(exprs);
+        compiler.chunk
+    }
+
+    /// Compiles a function body, where `return` is allowed and parameters are locals.
+    pub fn function(params: &Params, body: &Expr) -> Chunk {
+        let mut compiler = Compiler { chunk: Chunk::default(), span: None, in_function: true, loops: 0, resolver: Resolver::function(params) };
+        compiler.expr(body);
+        compiler.chunk
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.chunk.code.push(op);
+        self.chunk.spans.push(self.span);
+        self.chunk.code.len() - 1
+    }
+
+    /// Points the jump emitted at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.chunk.code.len();
+        match &mut self.chunk.code[at] {
+            Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfTrue(to)
+            | Op::JumpIfFalseOrPop(to) | Op::JumpIfTrueOrPop(to) | Op::ForNext(to) => *to = target,
+            Op::SetGuard { skip, .. } | Op::Callee { skip, .. } => *skip = target,
+            Op::LoopStart { exit, .. } => *exit = target,
+            _ => {},
+        }
+    }
+
+    fn constant(&mut self, value: Value) {
+        self.chunk.constants.push(value);
+        self.emit(Op::Constant(self.chunk.constants.len() - 1));
+    }
+
+    fn name(&mut self, name: Symbol) -> usize {
+        match self.chunk.names.iter().position(|&known| known == name) {
+            Some(index) => index,
+            None => {
+                self.chunk.names.push(name);
+                self.chunk.names.len() - 1
+            },
+        }
+    }
+
+    fn form(&mut self, expr: &Expr) -> usize {
+        self.chunk.forms.push(expr.clone());
+        self.chunk.forms.len() - 1
+    }
+
+    fn eval(&mut self, expr: &Expr) {
+        let form = self.form(expr);
+        self.emit(Op::Eval(form));
+    }
+
+    /// Leaves the value of the last expression, or nil when there is none.
+    fn sequence(&mut self, exprs: &[Expr]) {
+        if exprs.is_empty() {
+            self.constant(Value::Nil);
+        }
+        for (index, expr) in exprs.iter().enumerate() {
+            if index > 0 {
+                self.emit(Op::Pop);
+            }
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Number(n) => self.constant(Value::Number(*n)),
+            Expr::String(s) => self.constant(Value::String(s.clone())),
+            Expr::Symbol(s) => {
+                let name = self.name(*s);
+                let binding = self.resolver.resolve(*s);
+                self.emit(Op::Get { name, binding });
+            },
+            Expr::Array(items) => {
+                for item in items.iter() {
+                    self.expr(item);
+                }
+                self.emit(Op::Array(items.len()));
+            },
+            Expr::List(items, span) => {
+                let outer = self.span.replace(*span);
+                self.list(expr, items, *span);
+                self.span = outer;
+            },
+        }
+    }
+
+    fn list(&mut self, expr: &Expr, e: &[Expr], span: Span) {
+        let Some(head) = e.first() else {
+            self.constant(Value::Nil);
+            return;
+        };
+        if e.len() == 1 {
+            if let Expr::String(discard) = head && discard == "_" {
+                self.constant(Value::Nil);
+                return;
+            }
+            if !matches!(head, Expr::Symbol(_) | Expr::List(..)) {
+                self.expr(head);
+                return;
+            }
+        }
+        let Expr::Symbol(s) = head else {
+            self.eval(expr);
+            return;
+        };
+        let args = &e[1..];
+        match (*s, args) {
+            (symbol::DO, body) => self.sequence(body),
+            (symbol::IF, [condition, then_branch, rest @ ..]) if rest.len() <= 1 => {
+                self.expr(condition);
+                let to_else = self.emit(Op::JumpIfFalse(0));
+                self.expr(then_branch);
+                let to_end = self.emit(Op::Jump(0));
+                self.patch(to_else);
+                match rest.first() {
+                    Some(else_branch) => self.expr(else_branch),
+                    None => self.constant(Value::Nil),
+                }
+                self.patch(to_end);
+            },
+            (symbol::WHEN | symbol::UNLESS, [condition, body @ ..]) => {
+                self.expr(condition);
+                let to_skip = if *s == symbol::WHEN { self.emit(Op::JumpIfFalse(0)) } else { self.emit(Op::JumpIfTrue(0)) };
+                self.sequence(body);
+                let to_end = self.emit(Op::Jump(0));
+                self.patch(to_skip);
+                self.constant(Value::Nil);
+                self.patch(to_end);
+            },
+            (symbol::COND, clauses) if clauses.iter().all(|clause| matches!(clause, Expr::List(items, _) if !items.is_empty())) => {
+                let mut to_end = Vec::new();
+                for clause in clauses {
+                    let Expr::List(items, _) = clause else { continue };
+                    let (condition, body) = (&items[0], &items[1..]);
+                    if matches!(condition, Expr::Symbol(symbol::ELSE)) {
+                        self.sequence(body);
+                        to_end.push(self.emit(Op::Jump(0)));
+                        break;
+                    }
+                    self.expr(condition);
+                    let to_next = self.emit(Op::JumpIfFalse(0));
+                    self.sequence(body);
+                    to_end.push(self.emit(Op::Jump(0)));
+                    self.patch(to_next);
+                }
+                self.constant(Value::Nil);
+                for jump in to_end {
+                    self.patch(jump);
+                }
+            },
+            (symbol::AND | symbol::OR, []) => self.constant(Value::Bool(*s == symbol::AND)),
+            (symbol::AND | symbol::OR, operands) => {
+                let mut to_end = Vec::new();
+                for operand in &operands[..operands.len() - 1] {
+                    self.expr(operand);
+                    to_end.push(if *s == symbol::AND { self.emit(Op::JumpIfFalseOrPop(0)) } else { self.emit(Op::JumpIfTrueOrPop(0)) });
+                }
+                self.expr(&operands[operands.len() - 1]);
+                for jump in to_end {
+                    self.patch(jump);
+                }
+            },
+            (symbol::VAR, [Expr::Symbol(name), value, ..]) => {
+                self.expr(value);
+                self.resolver.declare(*name);
+                let name = self.name(*name);
+                self.emit(Op::Define(name));
+            },
+            (symbol::SET, [Expr::Symbol(target), value, ..]) => {
+                let name = self.name(*target);
+                let binding = self.resolver.resolve(*target);
+                let form = self.form(expr);
+                let guard = self.emit(Op::SetGuard { name, binding, form, skip: 0 });
+                self.expr(value);
+                self.emit(Op::Set { name, binding });
+                self.patch(guard);
+            },
+            (symbol::LET, [Expr::List(bindings, _), body @ ..]) if bindings.iter().all(|binding| matches!(binding, Expr::List(pair, _) if matches!(pair[..], [Expr::Symbol(_), _]))) => {
+                let mut names = Vec::new();
+                for binding in bindings.iter() {
+                    if let Expr::List(pair, _) = binding && let [Expr::Symbol(name), value] = &pair[..] {
+                        self.expr(value);
+                        names.push(*name);
+                    }
+                }
+                self.emit(Op::PushScope);
+                self.resolver.push();
+                for name in &names {
+                    self.resolver.declare(*name);
+                }
+                self.chunk.name_lists.push(names);
+                self.emit(Op::Bind(self.chunk.name_lists.len() - 1));
+                self.sequence(body);
+                self.resolver.pop();
+                self.emit(Op::PopScope);
+            },
+            (symbol::WHILE, [condition, body]) => {
+                self.constant(Value::Nil);
+                let start = self.emit(Op::LoopStart { exit: 0, next: 0, keep_last: true });
+                let next = self.chunk.code.len();
+                if let Op::LoopStart { next: to, .. } = &mut self.chunk.code[start] {
+                    *to = next;
+                }
+                self.expr(condition);
+                let to_end = self.emit(Op::JumpIfFalse(0));
+                self.emit(Op::LoopScope);
+                self.resolver.push();
+                self.loops += 1;
+                self.expr(body);
+                self.loops -= 1;
+                self.resolver.pop();
+                self.emit(Op::PopScope);
+                self.emit(Op::Nip);
+                self.emit(Op::Jump(next));
+                self.patch(to_end);
+                self.emit(Op::LoopEnd);
+                self.patch(start);
+            },
+            (symbol::FOR, [Expr::List(params, _), Expr::List(ranges, _), body, ..]) => {
+                let names: Vec<Symbol> = params.iter().filter_map(|param| match param {
+                    Expr::Symbol(name) => Some(*name),
+                    _ => None,
+                }).collect();
+                self.emit(Op::PushScope);
+                self.resolver.push();
+                for range in ranges.iter() {
+                    self.expr(range);
+                }
+                // Only as many names as ranges are bound.
+                for name in names.iter().take(ranges.len()) {
+                    self.resolver.declare(*name);
+                }
+                self.chunk.name_lists.push(names);
+                self.emit(Op::ForInit { names: self.chunk.name_lists.len() - 1, count: ranges.len() });
+                let start = self.emit(Op::LoopStart { exit: 0, next: 0, keep_last: false });
+                let next = self.emit(Op::ForNext(0));
+                if let Op::LoopStart { next: to, .. } = &mut self.chunk.code[start] {
+                    *to = next;
+                }
+                self.loops += 1;
+                self.expr(body);
+                self.loops -= 1;
+                self.emit(Op::Pop);
+                self.emit(Op::Jump(next));
+                self.patch(next);
+                self.emit(Op::LoopEnd);
+                self.constant(Value::Nil);
+                self.patch(start);
+                self.emit(Op::ForEnd);
+                self.resolver.pop();
+                self.emit(Op::PopScope);
+            },
+            (symbol::BREAK, rest) if self.loops > 0 => {
+                match rest.first() {
+                    Some(value) => self.expr(value),
+                    None => self.constant(Value::Nil),
+                }
+                self.emit(Op::Break);
+            },
+            (symbol::CONTINUE, _) if self.loops > 0 => {
+                self.emit(Op::Continue);
+            },
+            (symbol::RETURN, rest) if self.in_function => {
+                match rest.first() {
+                    Some(value) => self.expr(value),
+                    None => self.constant(Value::Nil),
+                }
+                self.emit(Op::Return);
+            },
+            (symbol::THREAD_FIRST | symbol::THREAD_LAST, [value, steps @ ..]) => {
+                let threaded = Interpreter::thread(value.clone(), steps.iter(), *s == symbol::THREAD_FIRST, span);
+                self.expr(&threaded);
+            },
+            (symbol::FN, [Expr::Symbol(name), ..]) => {
+                // Named functions are bound in the current scope by the tree walker.
+                self.resolver.declare(*name);
+                self.eval(expr);
+            },
+            (special, _) if Interpreter::is_special_form(special) => self.eval(expr),
+            (_, args) if args.iter().any(|arg| matches!(arg, Expr::Symbol(keyword) if keyword.is_keyword())) => {
+                self.eval(expr);
+            },
+            (function, args) => {
+                let name = self.name(function);
+                let binding = self.resolver.resolve(function);
+                let form = self.form(expr);
+                let callee = self.emit(Op::Callee { name, binding, form, skip: 0 });
+                for arg in args {
+                    self.expr(arg);
+                }
+                self.emit(Op::Call(args.len()));
+                self.patch(callee);
+            },
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::rc::Rc;
+use crate::core::error::Error;
+use crate::core::interpreter::Interpreter;
+use crate::core::lexer::Lexer;
+use crate::core::parser::Parser;
+use crate::core::signal::EvalResult;
+use crate::core::stdlib::{NativeFunction, NativeResult};
+use crate::core::symbol::Symbol;
+use crate::core::value::Value;
+
+/// API for host applications running scripts. Globals live in the scope where
+/// scripts run, so they shadow builtins of the same name.
+impl Interpreter {
+    /// Exposes a Rust closure to scripts as a native function. It may capture
+    /// state, such as a handle or a counter shared with the host.
+    pub fn register_fn(&mut self, name: &str, function: impl Fn(&[Value]) -> NativeResult + 'static) {
+        let native = NativeFunction::Closure(Rc::new(move |_, args, _| Ok(function(&args)?)));
+        self.set_global(name, Value::native(name, native));
+    }
+
+    /// Defines or replaces a global variable.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.env.borrow_mut().variables.insert(Symbol::from(name), value);
+    }
+
+    /// Value of a global variable or builtin.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.env.borrow().get(Symbol::from(name))
+    }
+
+    /// Calls the global function `name`, defined by a script, the host or the stdlib.
+    pub fn call_function(&mut self, name: &str, args: Vec<Value>) -> EvalResult {
+        let function = self.get_global(name).ok_or_else(|| Error::undefined(format!("Undefined function {}", name)))?;
+        self.call_value(function, args, self.env.clone())
+    }
+
+    /// Runs source code in the global scope and returns the value of its last form.
+    pub fn eval_str(&mut self, source: &str) -> EvalResult {
+        let tokens = Lexer::tokenize(source);
+        Parser::check(&tokens)?;
+        let exprs = Parser::parse(&mut tokens.into_iter().peekable());
+        self.execute(&exprs)
+    }
+}
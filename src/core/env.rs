@@ -1,43 +1,164 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use crate::core::class::Class;
+use crate::core::heap;
+use crate::core::macros::Macro;
+use crate::core::protocol::Protocol;
+use crate::core::symbol::Symbol;
 use crate::core::value::Value;
 
-#[derive(Debug)]
+/// Variables of one scope. Values sit in slots numbered in definition order, so
+/// that locals resolved by the compiler are read by index instead of by name.
+#[derive(Debug, Default)]
+pub struct Variables {
+    slots: HashMap<Symbol, usize>,
+    names: Vec<Symbol>,
+    values: Vec<Value>,
+    open: bool,
+}
+
+impl Variables {
+    pub fn get(&self, name: Symbol) -> Option<&Value> {
+        self.slots.get(&name).map(|&slot| &self.values[slot])
+    }
+
+    pub fn get_mut(&mut self, name: Symbol) -> Option<&mut Value> {
+        self.slots.get(&name).map(|&slot| &mut self.values[slot])
+    }
+
+    pub fn contains_key(&self, name: Symbol) -> bool {
+        self.slots.contains_key(&name)
+    }
+
+    /// Binds a name the resolver may not know about: addresses going through
+    /// this scope are not trusted anymore, see `is_open`.
+    pub fn insert(&mut self, name: Symbol, value: Value) {
+        if !self.slots.contains_key(&name) {
+            self.open = true;
+        }
+        self.declare(name, value);
+    }
+
+    /// Binds a name in the next free slot, or replaces its value.
+    pub fn declare(&mut self, name: Symbol, value: Value) {
+        match self.slots.get(&name) {
+            Some(&slot) => self.values[slot] = value,
+            None => {
+                self.slots.insert(name, self.values.len());
+                self.names.push(name);
+                self.values.push(value);
+            },
+        }
+    }
+
+    /// Value in `slot`, if that slot holds `name`.
+    pub fn slot(&self, slot: usize, name: Symbol) -> Option<&Value> {
+        match self.names.get(slot) {
+            Some(&held) if held == name => Some(&self.values[slot]),
+            _ => None,
+        }
+    }
+
+    pub fn slot_mut(&mut self, slot: usize, name: Symbol) -> Option<&mut Value> {
+        match self.names.get(slot) {
+            Some(&held) if held == name => Some(&mut self.values[slot]),
+            _ => None,
+        }
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.values.iter()
+    }
+
+    /// Whether a name was bound without going through `declare`, so that it may
+    /// shadow a local the compiler resolved further up.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+impl Extend<(String, Value)> for Variables {
+    fn extend<I: IntoIterator<Item = (String, Value)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.insert(Symbol::from(name), value);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct Env {
-    pub variables: HashMap<String, Value>,
+    pub variables: Variables,
     pub parent: Option<Rc<RefCell<Env>>>,
-    pub classes: HashMap<String, Vec<String>>
+    pub classes: HashMap<Symbol, Rc<Class>>,
+    pub protocols: HashMap<Symbol, Rc<Protocol>>,
+    pub macros: HashMap<Symbol, Rc<Macro>>,
 }
 
 impl Env {
-    pub fn get(&self, key: &str) -> Option<Value> {
+    /// New scope under `parent`, tracked by the cycle collector.
+    pub fn child(parent: &Rc<RefCell<Env>>) -> Rc<RefCell<Env>> {
+        let env = Rc::new(RefCell::new(Env {
+            parent: Some(parent.clone()),
+            ..Default::default()
+        }));
+        heap::track(&env);
+        env
+    }
+
+    pub fn get(&self, key: Symbol) -> Option<Value> {
+        self.inspect(key, Value::clone)
+    }
+
+    /// Applies `f` to the value of `key` where it is bound, for callers that
+    /// only look at it and would waste a clone with `get`.
+    pub fn inspect<R>(&self, key: Symbol, f: impl FnOnce(&Value) -> R) -> Option<R> {
         if let Some(val) = self.variables.get(key) {
-            Some(val.clone())
-        } else if let Some(parent) = &self.parent.clone() {
-            parent.borrow().get(key)
+            Some(f(val))
+        } else if let Some(parent) = &self.parent {
+            parent.borrow().inspect(key, f)
         } else {
-            return None;
+            None
         }
     }
 
-    pub fn set(&mut self, key: &str, value: Value) {
+    pub fn set(&mut self, key: Symbol, value: Value) {
         if let Some(val) = self.variables.get_mut(key) {
             *val = value;
-        } else if let Some(parent) = &self.parent.clone() {
+        } else if let Some(parent) = &self.parent {
             parent.borrow_mut().set(key, value);
         } else {
             panic!("Variable {} not found", key);
         }
     }
     
-    pub fn class_exists(&self, name: &str) -> Option<Vec<String>> {
-        if let Some(val) = self.classes.get(name) {
+    pub fn class_exists(&self, name: Symbol) -> Option<Rc<Class>> {
+        if let Some(val) = self.classes.get(&name) {
             Some(val.clone())
-        } else if let Some(parent) = &self.parent.clone() {
+        } else if let Some(parent) = &self.parent {
             parent.borrow().class_exists(name)
         } else {
             None
         }
     }
-}
\ No newline at end of file
+
+    pub fn protocol_exists(&self, name: Symbol) -> Option<Rc<Protocol>> {
+        if let Some(val) = self.protocols.get(&name) {
+            Some(val.clone())
+        } else if let Some(parent) = &self.parent {
+            parent.borrow().protocol_exists(name)
+        } else {
+            None
+        }
+    }
+
+    pub fn macro_exists(&self, name: Symbol) -> Option<Rc<Macro>> {
+        if let Some(val) = self.macros.get(&name) {
+            Some(val.clone())
+        } else if let Some(parent) = &self.parent {
+            parent.borrow().macro_exists(name)
+        } else {
+            None
+        }
+    }
+}
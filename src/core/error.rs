@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use crate::core::expr::Span;
+use crate::core::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    TypeError,
+    UndefinedSymbol,
+    ContractViolation,
+    SyntaxError,
+    RuntimeError,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorKind::TypeError => "TypeError",
+            ErrorKind::UndefinedSymbol => "UndefinedSymbol",
+            ErrorKind::ContractViolation => "ContractViolation",
+            ErrorKind::SyntaxError => "SyntaxError",
+            ErrorKind::RuntimeError => "RuntimeError",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An error raised by the interpreter or a native function. Scripts see it
+/// as an `Error` object once caught.
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub location: Option<Span>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Error {
+            kind,
+            message: message.into(),
+            location: None,
+        }
+    }
+
+    pub fn type_error(message: impl Into<String>) -> Self {
+        Error::new(ErrorKind::TypeError, message)
+    }
+
+    pub fn undefined(message: impl Into<String>) -> Self {
+        Error::new(ErrorKind::UndefinedSymbol, message)
+    }
+
+    pub fn contract(message: impl Into<String>) -> Self {
+        Error::new(ErrorKind::ContractViolation, message)
+    }
+
+    pub fn syntax(message: impl Into<String>) -> Self {
+        Error::new(ErrorKind::SyntaxError, message)
+    }
+
+    pub fn runtime(message: impl Into<String>) -> Self {
+        Error::new(ErrorKind::RuntimeError, message)
+    }
+
+    /// Converts the error into the `Error` object bound by `catch`.
+    pub fn to_value(&self) -> Value {
+        let mut attrs = HashMap::new();
+        attrs.insert("kind".to_string(), Value::String(self.kind.to_string()));
+        attrs.insert("message".to_string(), Value::String(self.message.clone()));
+        let (line, column) = match self.location {
+            Some(span) => (Value::Number(span.line as f32), Value::Number(span.column as f32)),
+            None => (Value::Nil, Value::Nil),
+        };
+        attrs.insert("line".to_string(), line);
+        attrs.insert("column".to_string(), column);
+        Value::Object {
+            class: "Error".to_string(),
+            attrs: Rc::new(attrs),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(span) => write!(f, "{} at {}: {}", self.kind, span, self.message),
+            None => write!(f, "{}: {}", self.kind, self.message),
+        }
+    }
+}
@@ -1,8 +1,116 @@
+use std::fmt;
+use std::rc::Rc;
+use crate::core::symbol::Symbol;
+
+/// Position of a form in the source file, 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A parsed form. Lists and arrays share their items, so cloning a form is cheap.
 #[derive(Debug, Clone)]
 pub enum Expr {
     Number(f32),
-    Symbol(String),
+    Symbol(Symbol),
     String(String),
-    List(Vec<Expr>),
-    Array(Vec<Expr>),
-}
\ No newline at end of file
+    List(Rc<[Expr]>, Span),
+    Array(Rc<[Expr]>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Symbol(s) => write!(f, "{}", s),
+            Expr::String(s) => write!(f, "\"{}\"", s),
+            Expr::List(items, _) => {
+                if let Some((prefix, form)) = self.reader_prefix() {
+                    return write!(f, "{}{}", prefix, form);
+                }
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "({})", items.join(" "))
+            },
+            Expr::Array(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(" "))
+            },
+        }
+    }
+}
+
+/// Column limit of `Expr::pretty` before a form is split over several lines.
+const PRETTY_WIDTH: usize = 80;
+
+impl Expr {
+    /// Source form of the expression, on one line when it fits, otherwise with
+    /// one argument per line indented under the head of the list.
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(0, &mut out);
+        out
+    }
+
+    fn write_pretty(&self, indent: usize, out: &mut String) {
+        let flat = self.to_string();
+        if indent + flat.len() <= PRETTY_WIDTH {
+            out.push_str(&flat);
+            return;
+        }
+        if let Some((prefix, form)) = self.reader_prefix() {
+            out.push_str(prefix);
+            form.write_pretty(indent + prefix.len(), out);
+            return;
+        }
+        let (open, close, items) = match self {
+            Expr::List(items, _) if items.len() > 1 => ("(", ")", items),
+            Expr::Array(items) if items.len() > 1 => ("[", "]", items),
+            _ => {
+                out.push_str(&flat);
+                return;
+            },
+        };
+        out.push_str(open);
+        // Lists keep their head and the atoms after it on the first line,
+        // arrays align every item.
+        let (first_line, item_indent) = if open == "(" {
+            let atoms = items[1..].iter().take_while(|item| !matches!(item, Expr::List(..) | Expr::Array(_))).count();
+            (1 + atoms, indent + 2)
+        } else {
+            (1, indent + 1)
+        };
+        let head: Vec<String> = items[..first_line].iter().map(|item| item.to_string()).collect();
+        out.push_str(&head.join(" "));
+        for item in &items[first_line..] {
+            out.push('\n');
+            out.push_str(&" ".repeat(item_indent));
+            item.write_pretty(item_indent, out);
+        }
+        out.push_str(close);
+    }
+
+    /// `(quote x)` and the other forms that the reader builds from a prefix.
+    fn reader_prefix(&self) -> Option<(&'static str, &Expr)> {
+        let Expr::List(items, _) = self else {
+            return None;
+        };
+        let [Expr::Symbol(head), form] = &items[..] else {
+            return None;
+        };
+        let prefix = match head.as_str() {
+            "quote" => "'",
+            "quasiquote" => "`",
+            "unquote" => "~",
+            "unquote-splicing" => "~@",
+            _ => return None,
+        };
+        Some((prefix, form))
+    }
+}
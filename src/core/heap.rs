@@ -0,0 +1,273 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+use crate::core::class::Class;
+use crate::core::env::Env;
+use crate::core::macros::Macro;
+use crate::core::protocol::Protocol;
+use crate::core::value::Value;
+
+/// Number of environments created before the first collection.
+const MIN_THRESHOLD: usize = 1024;
+
+/// Environments are reference counted, but a function stored in the scope it
+/// closes over keeps that scope alive: such cycles are never freed by counting
+/// alone. Every environment made by `Env::child` is tracked here, and once
+/// their number doubles the collector looks for the ones that only cycles hold.
+///
+/// Like symbols, the heap belongs to the thread that created the values.
+struct Heap {
+    envs: Vec<Weak<RefCell<Env>>>,
+    threshold: usize,
+    collections: usize,
+    collected: usize,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = const { RefCell::new(Heap { envs: Vec::new(), threshold: MIN_THRESHOLD, collections: 0, collected: 0 }) };
+}
+
+/// Memory usage as seen by the collector.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryStats {
+    /// Environments alive right now.
+    pub environments: usize,
+    /// Collections run so far.
+    pub collections: usize,
+    /// Environments freed by the collector so far, that counting alone could not free.
+    pub collected: usize,
+}
+
+impl MemoryStats {
+    /// The stats as a `Memory` object, for scripts.
+    pub fn to_value(&self) -> Value {
+        let mut attrs = HashMap::new();
+        attrs.insert("environments".to_string(), Value::Number(self.environments as f32));
+        attrs.insert("collections".to_string(), Value::Number(self.collections as f32));
+        attrs.insert("collected".to_string(), Value::Number(self.collected as f32));
+        Value::Object {
+            class: "Memory".to_string(),
+            attrs: Rc::new(attrs),
+        }
+    }
+}
+
+/// Tracks a new environment, collecting when enough of them were created since the last time.
+pub fn track(env: &Rc<RefCell<Env>>) {
+    let due = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.envs.push(Rc::downgrade(env));
+        heap.envs.len() >= heap.threshold
+    });
+    if due {
+        collect();
+    }
+}
+
+pub fn stats() -> MemoryStats {
+    HEAP.with(|heap| {
+        let heap = heap.borrow();
+        MemoryStats {
+            environments: heap.envs.iter().filter(|env| env.strong_count() > 0).count(),
+            collections: heap.collections,
+            collected: heap.collected,
+        }
+    })
+}
+
+/// Frees the environments that are only reachable from cycles and returns how
+/// many there were.
+///
+/// This is trial deletion: starting from the tracked environments, every value
+/// they hold is followed and the references found are counted. A node whose
+/// strong count is higher is also held from outside, by a running function or
+/// by the host, and so is everything it reaches. The rest is garbage, whose
+/// scopes and references are emptied to break the cycles.
+pub fn collect() -> usize {
+    let tracked: Vec<Rc<RefCell<Env>>> = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.envs.retain(|env| env.strong_count() > 0);
+        heap.envs.iter().filter_map(Weak::upgrade).collect()
+    });
+
+    let mut graph = Graph::default();
+    for env in tracked {
+        graph.add(Node::Env(env));
+    }
+    let mut next = 0;
+    while next < graph.nodes.len() {
+        let mut edges = Vec::new();
+        if !graph.nodes[next].edges(&mut edges) {
+            // Borrowed right now, so in use.
+            graph.roots.insert(next);
+        }
+        for edge in edges {
+            let index = graph.add(edge);
+            graph.internal[index] += 1;
+        }
+        next += 1;
+    }
+
+    let reachable = graph.reachable();
+    let mut freed = 0;
+    for (index, node) in graph.nodes.iter().enumerate() {
+        if reachable.contains(&index) {
+            continue;
+        }
+        match node {
+            Node::Env(env) => if let Ok(mut env) = env.try_borrow_mut() {
+                *env = Env::default();
+                freed += 1;
+            },
+            Node::Cell(cell) => if let Ok(mut cell) = cell.try_borrow_mut() {
+                *cell = Value::Nil;
+            },
+            _ => {},
+        }
+    }
+    drop(graph);
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.envs.retain(|env| env.strong_count() > 0);
+        heap.threshold = MIN_THRESHOLD.max(heap.envs.len() * 2);
+        heap.collections += 1;
+        heap.collected += freed;
+    });
+    freed
+}
+
+/// Shared allocations that can hold values, and so be part of a cycle.
+enum Node {
+    Env(Rc<RefCell<Env>>),
+    Items(Rc<Vec<Value>>),
+    Attrs(Rc<HashMap<String, Value>>),
+    Cell(Rc<RefCell<Value>>),
+    Class(Rc<Class>),
+    Protocol(Rc<Protocol>),
+    Macro(Rc<Macro>),
+}
+
+impl Node {
+    fn address(&self) -> *const () {
+        match self {
+            Node::Env(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Items(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Attrs(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Cell(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Class(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Protocol(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Macro(rc) => Rc::as_ptr(rc) as *const (),
+        }
+    }
+
+    /// References to the node, the one held by the graph excluded.
+    fn references(&self) -> usize {
+        let count = match self {
+            Node::Env(rc) => Rc::strong_count(rc),
+            Node::Items(rc) => Rc::strong_count(rc),
+            Node::Attrs(rc) => Rc::strong_count(rc),
+            Node::Cell(rc) => Rc::strong_count(rc),
+            Node::Class(rc) => Rc::strong_count(rc),
+            Node::Protocol(rc) => Rc::strong_count(rc),
+            Node::Macro(rc) => Rc::strong_count(rc),
+        };
+        count - 1
+    }
+
+    /// Pushes the nodes this one references, once per reference. Returns false
+    /// when the node cannot be looked into.
+    fn edges(&self, edges: &mut Vec<Node>) -> bool {
+        match self {
+            Node::Env(env) => {
+                let Ok(env) = env.try_borrow() else {
+                    return false;
+                };
+                edges.extend(env.parent.clone().map(Node::Env));
+                for value in env.variables.values() {
+                    value_edges(value, edges);
+                }
+                edges.extend(env.classes.values().cloned().map(Node::Class));
+                edges.extend(env.protocols.values().cloned().map(Node::Protocol));
+                edges.extend(env.macros.values().cloned().map(Node::Macro));
+            },
+            Node::Items(items) => items.iter().for_each(|value| value_edges(value, edges)),
+            Node::Attrs(attrs) => attrs.values().for_each(|value| value_edges(value, edges)),
+            Node::Cell(cell) => match cell.try_borrow() {
+                Ok(value) => value_edges(&value, edges),
+                Err(_) => return false,
+            },
+            Node::Class(class) => {
+                let Ok(methods) = class.methods.try_borrow() else {
+                    return false;
+                };
+                methods.values().for_each(|value| value_edges(value, edges));
+                class.fields.iter().filter_map(|field| field.default.as_ref()).for_each(|value| value_edges(value, edges));
+            },
+            Node::Protocol(protocol) => {
+                let Ok(impls) = protocol.impls.try_borrow() else {
+                    return false;
+                };
+                impls.values().flat_map(|methods| methods.values()).for_each(|value| value_edges(value, edges));
+            },
+            Node::Macro(definition) => value_edges(&definition.expander, edges),
+        }
+        true
+    }
+}
+
+fn value_edges(value: &Value, edges: &mut Vec<Node>) {
+    match value {
+        Value::Array(items) | Value::List(items) => edges.push(Node::Items(items.clone())),
+        Value::Object { attrs, .. } => edges.push(Node::Attrs(attrs.clone())),
+        Value::Ref(cell) => edges.push(Node::Cell(cell.clone())),
+        Value::Env(env) => edges.push(Node::Env(env.clone())),
+        Value::Ok(value) | Value::Err(value) => value_edges(value, edges),
+        Value::Function { func_env, .. } => edges.push(Node::Env(func_env.clone())),
+        Value::ProtocolMethod { protocol, .. } => edges.push(Node::Protocol(protocol.clone())),
+        // Native closures may capture values the collector cannot see, which then
+        // look referenced from outside: what they reach is kept.
+        _ => {},
+    }
+}
+
+/// Nodes found from the tracked environments, with the references counted between them.
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    indices: HashMap<*const (), usize>,
+    internal: Vec<usize>,
+    roots: HashSet<usize>,
+}
+
+impl Graph {
+    /// Index of the node, added when it is new. The graph holds one reference to each node.
+    fn add(&mut self, node: Node) -> usize {
+        let address = node.address();
+        if let Some(&index) = self.indices.get(&address) {
+            return index;
+        }
+        self.nodes.push(node);
+        self.internal.push(0);
+        self.indices.insert(address, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    /// Nodes held from outside the graph and everything they reference.
+    fn reachable(&self) -> HashSet<usize> {
+        let mut pending: Vec<usize> = (0..self.nodes.len())
+            .filter(|&index| self.roots.contains(&index) || self.nodes[index].references() > self.internal[index])
+            .collect();
+        let mut reachable: HashSet<usize> = pending.iter().copied().collect();
+        while let Some(index) = pending.pop() {
+            let mut edges = Vec::new();
+            self.nodes[index].edges(&mut edges);
+            for edge in edges {
+                if let Some(&target) = self.indices.get(&edge.address()) && reachable.insert(target) {
+                    pending.push(target);
+                }
+            }
+        }
+        reachable
+    }
+}
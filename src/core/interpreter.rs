@@ -1,339 +1,752 @@
-use std::cell::RefCell;
+use std::cell::{OnceCell, RefCell};
 use std::collections::HashMap;
 use std::iter::Peekable;
 use std::rc::Rc;
 use crate::core::annotation::Annotation;
+use crate::core::class::{Class, Field};
+use crate::core::compiler::{Chunk, Compiler};
 use crate::core::env::Env;
+use crate::core::error::Error;
+use crate::core::expr::Span;
+use crate::core::heap::{self, MemoryStats};
+use crate::core::macros::Macro;
+use crate::core::params::{Keywords, Params};
 use crate::core::parser::Expr;
+use crate::core::protocol::Protocol;
+use crate::core::signal::{EvalResult, Signal};
 use crate::core::stdlib::{NativeFunction, Stdlib};
+use crate::core::symbol::{self, Symbol};
 use crate::core::value::Value;
+use crate::core::vm::{ChunkCache, Engine};
+
+/// Type names that protocols can be implemented for besides struct classes.
+const BUILTIN_TYPES: [&str; 8] = ["Nil", "String", "Number", "Bool", "Array", "Function", "Symbol", "List"];
+
+/// Forms handled by `eval_list` itself rather than by a function call.
+const SPECIAL_FORMS: [Symbol; 33] = [
+    symbol::DO, symbol::IF, symbol::COND, symbol::WHEN, symbol::UNLESS, symbol::CASE, symbol::AND, symbol::OR,
+    symbol::VAR, symbol::STRUCT, symbol::PROTOCOL, symbol::IMPL, symbol::DOT, symbol::BREAK, symbol::CONTINUE,
+    symbol::RETURN, symbol::QUESTION, symbol::THROW, symbol::TRY, symbol::FOR, symbol::WHILE, symbol::THREAD_FIRST,
+    symbol::THREAD_LAST, symbol::THREAD_AS, symbol::LET, symbol::FN, symbol::DEFMACRO, symbol::QUOTE,
+    symbol::QUASIQUOTE, symbol::UNQUOTE, symbol::UNQUOTE_SPLICING, symbol::SET, symbol::MATCH,
+];
 
 pub struct Interpreter {
     pub env: Rc<RefCell<Env>>,
+    pub(crate) engine: Engine,
+    pub(crate) loop_depth: usize,
+    pub(crate) function_depth: usize,
+    gensym_counter: u32,
+    pub(crate) chunks: ChunkCache,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        let mut base = Env {
-            variables: HashMap::new(),
-            classes: HashMap::new(),
-            parent: None,
-        };
+        let mut base = Env::default();
         base.variables.extend(Stdlib::builtins());
+        // Caught errors are `Error` objects, scripts can build their own to throw.
+        base.classes.insert(Symbol::from("Error"), Rc::new(Class::new("Error", vec![
+            Field { name: "kind".to_string(), default: Some(Value::String("Error".to_string())) },
+            Field { name: "message".to_string(), default: Some(Value::String(String::new())) },
+            Field { name: "line".to_string(), default: Some(Value::Nil) },
+            Field { name: "column".to_string(), default: Some(Value::Nil) },
+        ])));
+        // Scripts run in a child scope so that `var` can shadow builtin names.
         Interpreter {
-            env: Rc::new(RefCell::new(base)),
+            env: Env::child(&Rc::new(RefCell::new(base))),
+            engine: Engine::Tree,
+            loop_depth: 0,
+            function_depth: 0,
+            gensym_counter: 0,
+            chunks: ChunkCache::default(),
+        }
+    }
+
+    pub fn with_engine(engine: Engine) -> Self {
+        Interpreter { engine, ..Self::new() }
+    }
+
+    /// Memory usage of the values made on this thread, see `heap`. The heap is
+    /// shared by every interpreter of the thread, so the stats count theirs too.
+    pub fn memory(&self) -> MemoryStats {
+        heap::stats()
+    }
+
+    /// Frees the environments only kept alive by reference cycles, returns how
+    /// many. Like `memory`, this covers every interpreter of the thread.
+    pub fn collect_garbage(&mut self) -> usize {
+        heap::collect()
+    }
+
+    /// Runs a parsed program in the global scope with the selected engine.
+    pub fn execute(&mut self, exprs: &[Expr]) -> EvalResult {
+        match self.engine {
+            Engine::Tree => self.compute(&mut exprs.iter().peekable(), self.env.clone()),
+            Engine::Vm => self.run_chunk(&Compiler::program(exprs), self.env.clone()),
         }
     }
-    pub fn compute<'a>(&mut self, exprs: &mut Peekable<impl Iterator<Item = &'a Expr>>, env: Rc<RefCell<Env>>) -> Value  {
+    pub fn compute<'a>(&mut self, exprs: &mut Peekable<impl Iterator<Item = &'a Expr>>, env: Rc<RefCell<Env>>) -> EvalResult {
         let mut result: Value = Value::Nil;
-        while let Some(expr) = exprs.next() {
+        for expr in exprs.by_ref() {
             match expr {
                 Expr::Number(n) => result = self.handle_number(*n),
                 Expr::String(s) => result = self.handle_string(s.clone()),
-                Expr::Array(arr) => result = self.handle_array(arr.clone(), env.clone()),
-                Expr::Symbol(s) => result = self.handle_symbol(s.to_string(), env.clone()),
-                Expr::List(e) => result = self.handle_list(e, env.clone())
+                Expr::Array(arr) => result = self.handle_array(arr, env.clone())?,
+                Expr::Symbol(s) => result = self.handle_symbol(*s, env.clone())?,
+                Expr::List(e, span) => result = self.handle_list(e, *span, env.clone())?
+            }
+        }
+        Ok(result)
+    }
+
+    /// Generates a symbol name that no script wrote, for macro hygiene.
+    pub fn gensym(&mut self, prefix: Symbol) -> Symbol {
+        self.gensym_counter += 1;
+        Symbol::gensym(prefix, self.gensym_counter)
+    }
+
+    pub(crate) fn is_special_form(name: Symbol) -> bool {
+        SPECIAL_FORMS.contains(&name)
+    }
+
+    /// Calls a function value (native or user defined) with already evaluated arguments.
+    pub fn call_value(&mut self, function: Value, args: Vec<Value>, env: Rc<RefCell<Env>>) -> EvalResult {
+        self.call_with_keywords(function, args, Vec::new(), env)
+    }
+
+    /// Like `call_value`, with `:name value` arguments that only user functions accept.
+    fn call_with_keywords(&mut self, function: Value, args: Vec<Value>, keywords: Keywords, env: Rc<RefCell<Env>>) -> EvalResult {
+        if !keywords.is_empty() && !matches!(function, Value::Function { .. }) {
+            return Err(Error::type_error("Keyword arguments are only accepted by user functions").into());
+        }
+        match function {
+            Value::NativeFunction(native) => match &native.function {
+                NativeFunction::Pure(fp) => Ok(fp(args)?),
+                NativeFunction::WithEnv(fwe) => Ok(fwe(args, env)?),
+                NativeFunction::WithInterpreter(fwi) => fwi(self, args, env),
+                NativeFunction::Closure(closure) => closure(self, args, env),
+            },
+            Value::Function { params, body, func_env, annotations, chunk, .. } => {
+                let local_env = Env::child(&func_env);
+                self.bind_params(&params, args, keywords, local_env.clone())?;
+
+                for annotation in annotations.iter() {
+                    if let Annotation::Require(requirement) = annotation {
+                        let satisfied = self.compute(&mut std::iter::once(requirement).peekable(), local_env.clone())?;
+                        if !matches!(satisfied, Value::Bool(true)) {
+                            return Err(Error::contract(format!("Requirement {} is not satisfied", requirement)).into());
+                        }
+                    }
+                }
+
+                self.call_body(&params, &body, &chunk, local_env)
+            },
+            Value::ProtocolMethod { protocol, method } => {
+                let type_name = match args.first() {
+                    Some(receiver) => receiver.type_name(),
+                    None => return Err(Error::type_error(format!("{} of protocol {} misses parameters !", method, protocol.name)).into()),
+                };
+                let implementation = protocol.dispatch(&type_name, &method)
+                    .ok_or_else(|| Error::type_error(format!("Protocol {} is not implemented for type {} (missing {})", protocol.name, type_name, method)))?;
+                self.call_value(implementation, args, env)
+            },
+            other => Err(Error::type_error(format!("{} is not a function", other)).into()),
+        }
+    }
+
+    /// Binds call arguments in `local_env`: positional ones first, then keywords,
+    /// extra arguments go to the rest parameter and unbound ones take their default.
+    fn bind_params(&mut self, params: &Params, args: Vec<Value>, keywords: Keywords, local_env: Rc<RefCell<Env>>) -> Result<(), Signal> {
+        let given = args.len() + keywords.len();
+        let mut args = args.into_iter();
+        let mut values: Vec<Option<Value>> = params.positional.iter().map(|_| args.next()).collect();
+        let rest: Vec<Value> = args.collect();
+        if params.rest.is_none() && !rest.is_empty() {
+            return Err(Error::type_error(format!("Function expects {} arguments, got {}", params.arity(), given)).into());
+        }
+        for (name, value) in keywords {
+            let index = params.positional.iter().position(|param| param.name == name)
+                .ok_or_else(|| Error::type_error(format!("Unknown keyword argument :{}", name)))?;
+            if values[index].replace(value).is_some() {
+                return Err(Error::type_error(format!("Argument {} given twice", name)).into());
+            }
+        }
+        // Parameters are declared in order, then the rest parameter, so that their slots are the
+        // ones `Resolver::function` gives. Defaults are evaluated in that order too, so they can
+        // refer to previous parameters.
+        for (param, value) in params.positional.iter().zip(values) {
+            let value = match (value, &param.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => self.compute(&mut std::iter::once(default).peekable(), local_env.clone())?,
+                (None, None) => return Err(Error::type_error(format!("Missing argument {} (function expects {} arguments, got {})", param.name, params.arity(), given)).into()),
+            };
+            local_env.borrow_mut().variables.declare(param.name, value);
+        }
+        if let Some(name) = params.rest {
+            local_env.borrow_mut().variables.declare(name, Value::Array(Rc::new(rest)));
+        }
+        Ok(())
+    }
+
+    /// Evaluates call arguments, splitting `:name value` pairs from positional ones.
+    fn call_args<'a>(&mut self, args: impl Iterator<Item = &'a Expr>, env: Rc<RefCell<Env>>) -> Result<(Vec<Value>, Keywords), Signal> {
+        let mut positional = Vec::new();
+        let mut keywords = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if let Expr::Symbol(keyword) = arg && keyword.is_keyword() {
+                let value_expr = args.next().ok_or_else(|| Error::syntax(format!("Missing value for keyword argument {}", keyword)))?;
+                let value = self.compute(&mut std::iter::once(value_expr).peekable(), env.clone())?;
+                keywords.push((Symbol::from(&keyword[1..]), value));
+            } else {
+                positional.push(self.compute(&mut std::iter::once(arg).peekable(), env.clone())?);
             }
         }
-        result
+        Ok((positional, keywords))
     }
 
     //     ╭────────────────╮
     //     │    Handlers    │
     //     ╰────────────────╯
-    fn handle_list(&mut self, e: &Vec<Expr>, env: Rc<RefCell<Env>>) -> Value {
-        if e.len() == 0 {
-            return Value::Nil;
+    /// Evaluates a function body in its call environment, turning `return` into the call result.
+    fn call_body(&mut self, params: &Params, body: &Rc<Expr>, chunk: &OnceCell<Rc<Chunk>>, local_env: Rc<RefCell<Env>>) -> EvalResult {
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        self.function_depth += 1;
+        let result = match self.engine {
+            Engine::Tree => self.compute(&mut std::iter::once(body.as_ref()).peekable(), local_env),
+            Engine::Vm => self.run_compiled(params, body, chunk, local_env),
+        };
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+        match result {
+            Err(Signal::Return(value)) => Ok(value),
+            other => other,
+        }
+    }
+
+    /// Runs one iteration of a loop body. Returns `Ok(None)` to keep looping and
+    /// `Ok(Some(value))` when the body asked to `break`.
+    fn loop_body(&mut self, body: &Expr, env: Rc<RefCell<Env>>, last: &mut Value) -> Result<Option<Value>, Signal> {
+        self.loop_depth += 1;
+        let result = self.compute(&mut std::iter::once(body).peekable(), env);
+        self.loop_depth -= 1;
+        match result {
+            Ok(value) => {
+                *last = value;
+                Ok(None)
+            },
+            Err(Signal::Continue) => Ok(None),
+            Err(Signal::Break(value)) => Ok(Some(value)),
+            Err(signal) => Err(signal),
+        }
+    }
+
+    /// Evaluates a condition by truthiness, see `Value::is_truthy`.
+    fn test(&mut self, condition: &Expr, env: Rc<RefCell<Env>>) -> Result<bool, Signal> {
+        let value = self.compute(&mut std::iter::once(condition).peekable(), env)?;
+        Ok(value.is_truthy())
+    }
+
+    /// `case` keys are literals compared to the value without being evaluated:
+    /// numbers, strings, `true`, `false` and `nil`.
+    fn case_key_matches(key: &Expr, value: &Value) -> Result<bool, Error> {
+        Ok(match (key, value) {
+            (Expr::Number(k), Value::Number(v)) => k == v,
+            (Expr::String(k), Value::String(v)) => k == v,
+            (Expr::Number(_) | Expr::String(_), _) => false,
+            (Expr::Symbol(symbol::NIL), value) => matches!(value, Value::Nil),
+            (Expr::Symbol(symbol::TRUE), value) => matches!(value, Value::Bool(true)),
+            (Expr::Symbol(symbol::FALSE), value) => matches!(value, Value::Bool(false)),
+            (key, _) => return Err(Error::syntax(format!("Invalid case key {}, keys are numbers, strings, true, false or nil", key))),
+        })
+    }
+
+    fn handle_list(&mut self, e: &[Expr], span: Span, env: Rc<RefCell<Env>>) -> EvalResult {
+        match self.eval_list(e, span, env) {
+            // The innermost form that failed gives its location to the error.
+            Err(Signal::Error(mut error)) if error.location.is_none() => {
+                error.location = Some(span);
+                Err(Signal::Error(error))
+            },
+            result => result,
+        }
+    }
+
+    fn eval_list(&mut self, e: &[Expr], span: Span, env: Rc<RefCell<Env>>) -> EvalResult {
+        if e.is_empty() {
+            return Ok(Value::Nil);
         }
         if e.len() == 1 {
-            if let Some(Expr::String(discard)) = e.first() {
-                if discard == "_" {
-                    return Value::Nil;
-                }
+            if let Some(Expr::String(discard)) = e.first() && discard == "_" {
+                return Ok(Value::Nil);
+            }
+            if !matches!(e[0], Expr::Symbol(_) | Expr::List(..)) {
+                return self.compute(&mut std::iter::once(&e[0]).peekable(), env.clone())
             }
-            return self.compute(&mut std::iter::once(&e[0]).peekable(), env.clone())
         }
         let mut result = Value::Nil;
-        let mut args = e[1..e.len()].into_iter().peekable();
+        let mut args = e[1..e.len()].iter().peekable();
         match &e[0] {
-            Expr::Symbol(s) if s == "do" => {
+            Expr::Symbol(symbol::DO) => {
                 for arg in args {
                     let mut i = std::iter::once(arg).peekable();
-                    result = self.compute(&mut i, env.clone())
+                    result = self.compute(&mut i, env.clone())?
                 }
             },
-            Expr::Symbol(s) if s == "if" => {
-                let condition = self.compute(&mut std::iter::once(&e[1]).peekable(), env.clone());
-                if let Value::Bool(b) = condition {
-                    if b {
-                        let e1 = self.compute(&mut std::iter::once(&e[2]).peekable(), env.clone());
-                        result = e1;
-                    } else {
-                        let e2 = self.compute(&mut std::iter::once(&e[3]).peekable(), env.clone());
-                        result = e2;
+            Expr::Symbol(symbol::IF) => {
+                let condition = args.next().ok_or_else(|| Error::syntax("if expects a condition"))?;
+                let then_branch = args.next().ok_or_else(|| Error::syntax("if expects a branch"))?;
+                let else_branch = args.next();
+                if args.next().is_some() {
+                    return Err(Error::syntax("if expects at most two branches").into());
+                }
+                if self.test(condition, env.clone())? {
+                    result = self.compute(&mut std::iter::once(then_branch).peekable(), env.clone())?;
+                } else if let Some(else_branch) = else_branch {
+                    result = self.compute(&mut std::iter::once(else_branch).peekable(), env.clone())?;
+                }
+            },
+            Expr::Symbol(symbol::COND) => {
+                for clause in args.by_ref() {
+                    let Expr::List(clause, _) = clause else {
+                        return Err(Error::syntax("cond clauses should be lists").into());
+                    };
+                    let (condition, body) = clause.split_first().ok_or_else(|| Error::syntax("cond clause expects a condition"))?;
+                    let selected = match condition {
+                        Expr::Symbol(symbol::ELSE) => true,
+                        _ => self.test(condition, env.clone())?,
+                    };
+                    if selected {
+                        result = self.compute(&mut body.iter().peekable(), env.clone())?;
+                        break;
+                    }
+                }
+            },
+            Expr::Symbol(s @ (symbol::WHEN | symbol::UNLESS)) => {
+                let condition = args.next().ok_or_else(|| Error::syntax(format!("{} expects a condition", s)))?;
+                if self.test(condition, env.clone())? == (*s == symbol::WHEN) {
+                    result = self.compute(&mut args, env.clone())?;
+                }
+            },
+            Expr::Symbol(symbol::CASE) => {
+                let value_expr = args.next().ok_or_else(|| Error::syntax("case expects a value"))?;
+                let value = self.compute(&mut std::iter::once(value_expr).peekable(), env.clone())?;
+                for clause in args.by_ref() {
+                    let Expr::List(clause, _) = clause else {
+                        return Err(Error::syntax("case clauses should be lists").into());
+                    };
+                    let (keys, body) = clause.split_first().ok_or_else(|| Error::syntax("case clause expects a key"))?;
+                    let selected = match keys {
+                        Expr::Symbol(symbol::ELSE) => true,
+                        Expr::List(keys, _) => keys.iter().try_fold(false, |found, key| Ok::<_, Error>(found | Self::case_key_matches(key, &value)?))?,
+                        key => Self::case_key_matches(key, &value)?,
+                    };
+                    if selected {
+                        result = self.compute(&mut body.iter().peekable(), env.clone())?;
+                        break;
+                    }
+                }
+            },
+            Expr::Symbol(s @ (symbol::AND | symbol::OR)) => {
+                // Both stop at the first operand that decides the outcome and return it.
+                let is_and = *s == symbol::AND;
+                result = Value::Bool(is_and);
+                for operand in args.by_ref() {
+                    result = self.compute(&mut std::iter::once(operand).peekable(), env.clone())?;
+                    if result.is_truthy() != is_and {
+                        break;
                     }
-                } else {
-                    result = Value::Nil;
                 }
             },
-            Expr::Symbol(s) if s == "var" => {
+            Expr::Symbol(symbol::VAR) => {
                 let arg1 = args.next();
                 if let Some(Expr::Symbol(name)) = arg1 {
                     let arg2 = args.next();
                     if let Some(v) = arg2 {
-                        let value = self.compute(&mut std::iter::once(v).peekable(), env.clone());
-                        if env.borrow_mut().variables.contains_key(name) {
-                            panic!("Variable {} already defined ! Use set to modify its value.", name);
+                        let value = self.compute(&mut std::iter::once(v).peekable(), env.clone())?;
+                        if env.borrow_mut().variables.contains_key(*name) {
+                            return Err(Error::runtime(format!("Variable {} already defined ! Use set to modify its value.", name)).into());
                         } else {
-                            env.clone().borrow_mut().variables.insert(name.to_string(), value.clone());
+                            env.clone().borrow_mut().variables.insert(*name, value.clone());
                             result = value;
                         }
                     }
                 }
             },
-            Expr::Symbol(s) if s == "struct" => {
-                let name_expr = args.next().unwrap();
+            Expr::Symbol(symbol::STRUCT) => {
+                let name_expr = args.next().ok_or_else(|| Error::syntax("struct expects a name"))?;
                 if let Expr::Symbol(name) = name_expr {
-                    let attrs_expr = args.next().unwrap();
-                    let mut attrs: Vec<String> = Vec::new();
-                    if let Expr::List(a) = attrs_expr {
-                        for i in a {
-                            if let Expr::Symbol(n) = i {
-                                attrs.push(n.to_string());
+                    let attrs_expr = args.next().ok_or_else(|| Error::syntax("struct expects a list of fields"))?;
+                    let mut attrs: Vec<Field> = Vec::new();
+                    if let Expr::List(a, _) = attrs_expr {
+                        for i in a.iter() {
+                            match i {
+                                Expr::Symbol(n) => attrs.push(Field { name: n.to_string(), default: None }),
+                                Expr::List(pair, _) => {
+                                    if let [Expr::Symbol(n), default] = &pair[..] {
+                                        let value = self.compute(&mut std::iter::once(default).peekable(), env.clone())?;
+                                        attrs.push(Field { name: n.to_string(), default: Some(value) });
+                                    } else {
+                                        return Err(Error::syntax("Field with default value should contain a name and a value !").into());
+                                    }
+                                },
+                                _ => return Err(Error::syntax(format!("Invalid field declaration in struct {}", name)).into()),
                             }
                         }
-                        env.borrow_mut().classes.insert(name.to_string(), attrs);
+                        env.borrow_mut().classes.insert(*name, Rc::new(Class::new(&name.to_string(), attrs)));
                     }
                 }
             },
-            Expr::Symbol(s) if s == "for" => {
-                let local_env = Rc::new(RefCell::new(
-                    Env {
-                        variables: Default::default(),
-                        classes: Default::default(),
-                        parent: Some(env.clone())
+            Expr::Symbol(symbol::PROTOCOL) => {
+                let name = match args.next() {
+                    Some(Expr::Symbol(name)) => name,
+                    _ => return Err(Error::syntax("protocol expects a name").into()),
+                };
+                let mut methods: Vec<(String, Vec<String>)> = Vec::new();
+                for signature in args {
+                    if let Expr::List(signature, _) = signature
+                        && let (Some(Expr::Symbol(method)), Some(Expr::List(params, _))) = (signature.first(), signature.get(1)) {
+                        let params: Vec<String> = params.iter().filter_map(|param| {
+                            if let Expr::Symbol(param) = param {
+                                Some(param.to_string())
+                            } else {
+                                None
+                            }
+                        }).collect();
+                        if params.is_empty() {
+                            return Err(Error::syntax(format!("Method {} of protocol {} needs at least one parameter to dispatch on", method, name)).into());
+                        }
+                        methods.push((method.to_string(), params));
+                    } else {
+                        return Err(Error::syntax(format!("Invalid method signature in protocol {}", name)).into());
+                    }
+                }
+                let protocol = Rc::new(Protocol::new(&name.to_string(), methods));
+                let mut ev = env.borrow_mut();
+                for (method, _) in &protocol.methods {
+                    ev.variables.insert(Symbol::from(method), Value::ProtocolMethod {
+                        protocol: protocol.clone(),
+                        method: method.clone(),
+                    });
+                }
+                ev.protocols.insert(*name, protocol);
+            },
+            Expr::Symbol(symbol::IMPL) => {
+                let type_name = match args.next() {
+                    Some(Expr::Symbol(name)) => name,
+                    _ => return Err(Error::syntax("impl expects a class name").into()),
+                };
+                let class = env.borrow().class_exists(*type_name);
+                let protocol = match args.peek() {
+                    Some(Expr::Symbol(name)) => {
+                        args.next();
+                        let protocol_opt = env.borrow().protocol_exists(*name);
+                        Some(protocol_opt.ok_or_else(|| Error::undefined(format!("Undefined protocol {}", name)))?)
+                    },
+                    _ => None,
+                };
+                if class.is_none() && (protocol.is_none() || !BUILTIN_TYPES.contains(&type_name.as_str())) {
+                    return Err(Error::undefined(format!("Undefined class {}", type_name)).into());
+                }
+                for method in args {
+                    match method {
+                        Expr::List(definition, _) if matches!(definition.first(), Some(Expr::Symbol(symbol::FN))) => {
+                            let (method_name, function) = self.make_function(definition, env.clone())?;
+                            if let Some(protocol) = &protocol {
+                                let expected = protocol.signature(&method_name)
+                                    .ok_or_else(|| Error::undefined(format!("{} is not a method of protocol {}", method_name, protocol.name)))?;
+                                if let Value::Function { params, .. } = &function
+                                    && params.positional.len() != expected.len() {
+                                    return Err(Error::type_error(format!("Method {} of protocol {} expects {} parameters, got {}", method_name, protocol.name, expected.len(), params.positional.len())).into());
+                                }
+                                protocol.impls.borrow_mut()
+                                    .entry(type_name.to_string())
+                                    .or_default()
+                                    .insert(method_name.clone(), function.clone());
+                            }
+                            if let Some(class) = &class {
+                                class.methods.borrow_mut().insert(method_name.clone(), function.clone());
+                            }
+                            self.run_tests(&method_name, &function, env.clone())?;
+                        },
+                        _ => return Err(Error::syntax(format!("impl {} only accepts fn definitions", type_name)).into()),
+                    }
+                }
+            },
+            Expr::Symbol(symbol::DOT) => {
+                let object = self.compute(&mut std::iter::once(args.next().ok_or_else(|| Error::syntax("< . > expects an object"))?).peekable(), env.clone())?;
+                let method_name = match args.next() {
+                    Some(Expr::Symbol(name)) => name,
+                    _ => return Err(Error::syntax("< . > expects a method name").into()),
+                };
+                let method = match &object {
+                    Value::Object { class, attrs: _ } => {
+                        let class_opt = env.borrow().class_exists(Symbol::from(class));
+                        let class = class_opt.ok_or_else(|| Error::undefined(format!("Undefined class {}", class)))?;
+                        class.method(method_name)
+                            .ok_or_else(|| Error::undefined(format!("Undefined method {} for class {}", method_name, class.name)))?
+                    },
+                    other => return Err(Error::type_error(format!("Cannot call method {} on {}", method_name, other)).into()),
+                };
+                let mut arg_values = vec![object];
+                for arg in args {
+                    arg_values.push(self.compute(&mut std::iter::once(arg).peekable(), env.clone())?);
+                }
+                result = self.call_value(method, arg_values, env.clone())?;
+            },
+            Expr::Symbol(s @ (symbol::BREAK | symbol::CONTINUE)) => {
+                if self.loop_depth == 0 {
+                    return Err(Error::syntax(format!("{} outside of a loop", s)).into());
+                }
+                if *s == symbol::CONTINUE {
+                    return Err(Signal::Continue);
+                }
+                let value = match args.next() {
+                    Some(value_expr) => self.compute(&mut std::iter::once(value_expr).peekable(), env.clone())?,
+                    None => Value::Nil,
+                };
+                return Err(Signal::Break(value));
+            },
+            Expr::Symbol(symbol::RETURN) => {
+                if self.function_depth == 0 {
+                    return Err(Error::syntax("return outside of a function").into());
+                }
+                let value = match args.next() {
+                    Some(value_expr) => self.compute(&mut std::iter::once(value_expr).peekable(), env.clone())?,
+                    None => Value::Nil,
+                };
+                return Err(Signal::Return(value));
+            },
+            Expr::Symbol(symbol::QUESTION) => {
+                let value_expr = args.next().ok_or_else(|| Error::syntax("? expects a value"))?;
+                match self.compute(&mut std::iter::once(value_expr).peekable(), env.clone())? {
+                    Value::Ok(value) => result = *value,
+                    failure @ Value::Err(_) => {
+                        if self.function_depth == 0 {
+                            return Err(Error::syntax("? outside of a function").into());
+                        }
+                        return Err(Signal::Return(failure));
+                    },
+                    other => return Err(Error::type_error(format!("? expects a result, got {}", other.type_name())).into()),
+                }
+            },
+            Expr::Symbol(symbol::THROW) => {
+                let value_expr = args.next().ok_or_else(|| Error::syntax("throw expects a value"))?;
+                let value = self.compute(&mut std::iter::once(value_expr).peekable(), env.clone())?;
+                return Err(Signal::Throw(value));
+            },
+            Expr::Symbol(symbol::TRY) => {
+                let mut body: Vec<&Expr> = Vec::new();
+                let mut catch: Option<(Symbol, &[Expr])> = None;
+                let mut finally: Option<&[Expr]> = None;
+                for arg in args {
+                    match arg {
+                        Expr::List(clause, _) if matches!(clause.first(), Some(Expr::Symbol(symbol::CATCH))) => {
+                            match clause.get(1) {
+                                Some(Expr::Symbol(name)) => catch = Some((*name, &clause[2..])),
+                                _ => return Err(Error::syntax("catch expects a variable name").into()),
+                            }
+                        },
+                        Expr::List(clause, _) if matches!(clause.first(), Some(Expr::Symbol(symbol::FINALLY))) => {
+                            finally = Some(&clause[1..]);
+                        },
+                        _ => body.push(arg),
                     }
-                ));
-                let mut p: Vec<String> = Vec::new();
+                }
+                let mut outcome = self.compute(&mut body.into_iter().peekable(), env.clone());
+                if let Some((name, handler)) = catch {
+                    // Only errors are caught, break/continue/return keep unwinding.
+                    let caught = match &outcome {
+                        Err(Signal::Throw(value)) => Some(value.clone()),
+                        Err(Signal::Error(error)) => Some(error.to_value()),
+                        _ => None,
+                    };
+                    if let Some(value) = caught {
+                        let catch_env = Env::child(&env);
+                        catch_env.borrow_mut().variables.insert(name, value);
+                        outcome = self.compute(&mut handler.iter().peekable(), catch_env);
+                    }
+                }
+                if let Some(cleanup) = finally {
+                    self.compute(&mut cleanup.iter().peekable(), env.clone())?;
+                }
+                result = outcome?;
+            },
+            Expr::Symbol(symbol::FOR) => {
+                let local_env = Env::child(&env);
+                let mut p: Vec<Symbol> = Vec::new();
                 let mut r: Vec<Vec<f32>> = Vec::new();
-                if let Some(Expr::List(params)) = args.next() {
-                    for param in params {
-                        if let Expr::Symbol(param_name) = &*param {
-                            p.push(param_name.to_string());
+                if let Some(Expr::List(params, _)) = args.next() {
+                    for param in params.iter() {
+                        if let Expr::Symbol(param_name) = param {
+                            p.push(*param_name);
                         }
                     }
                 }
-                if let Some(Expr::List(ranges)) = args.next() {
-                    for range in ranges {
-                        let value_expr = self.compute(&mut std::iter::once(range).peekable(), local_env.clone());
+                if let Some(Expr::List(ranges, _)) = args.next() {
+                    for range in ranges.iter() {
+                        let value_expr = self.compute(&mut std::iter::once(range).peekable(), local_env.clone())?;
                         if let Value::Array(arr) = value_expr {
                             let mut values: Vec<f32> = Vec::new();
-                            for value in arr {
+                            for value in arr.iter() {
                                 if let Value::Number(n) = value {
-                                    values.push(n);
+                                    values.push(*n);
                                 }
                             }
                             r.push(values);
                         }
                     }
                 }
-                let ast = args.next().unwrap();
-                let max = r.clone().into_iter().map(|n| n.iter().count()).min().unwrap();
+                let ast = args.next().ok_or_else(|| Error::syntax("for expects a body"))?;
+                let max = r.iter().map(|n| n.len()).min().unwrap_or(0);
+                let mut last = Value::Nil;
                 for i in 0..max {
-                    for (n,j) in p.iter().zip(0..p.iter().count()) {
+                    for (n, values) in p.iter().zip(r.iter()) {
                         local_env.borrow_mut().variables.insert(
-                            n.to_string(),
-                            Value::Number(r[j][i])
+                            *n,
+                            Value::Number(values[i])
                         );
                     }
-                    self.compute(&mut std::iter::once(ast).peekable(), local_env.clone());
+                    if let Some(value) = self.loop_body(ast, local_env.clone(), &mut last)? {
+                        result = value;
+                        break;
+                    }
                 }
 
             },
-            Expr::Symbol(s) if s == "while" => {
-                let local_env = Rc::new(RefCell::new(
-                    Env {
-                        variables: Default::default(),
-                        classes: Default::default(),
-                        parent: Some(env.clone())
-                    }
-                ));
-                let condition = args.next().unwrap();
-                let body = args.next().unwrap();
+            Expr::Symbol(symbol::WHILE) => {
+                let local_env = Env::child(&env);
+                let condition = args.next().ok_or_else(|| Error::syntax("while expects a condition"))?;
+                let body = args.next().ok_or_else(|| Error::syntax("while expects a body"))?;
                 let mut value = Value::Nil;
-                loop {
-                    if let Value::Bool(b) = self.compute(&mut std::iter::once(condition).peekable(), env.clone()) {
-                        if b {
-                            value = self.compute(&mut std::iter::once(body).peekable(), local_env.clone());
-                        } else {
-                            break;
-                        }
-                    } else {
-                        panic!("Non boolean condition found.");
+                while self.test(condition, env.clone())? {
+                    if let Some(broken) = self.loop_body(body, local_env.clone(), &mut value)? {
+                        value = broken;
+                        break;
                     }
                 }
                 result = value;
             },
-            Expr::Symbol(s) if s == "let" => {
-                let local_env = Rc::new(RefCell::new(
-                    Env {
-                        variables: Default::default(),
-                        classes: Default::default(),
-                        parent: Some(env.clone()),
-                    }
-                ));
+            Expr::Symbol(s @ (symbol::THREAD_FIRST | symbol::THREAD_LAST)) => {
+                let value = args.next().ok_or_else(|| Error::syntax(format!("{} expects a value", s)))?;
+                let threaded = Self::thread(value.clone(), args, *s == symbol::THREAD_FIRST, span);
+                result = self.compute(&mut std::iter::once(&threaded).peekable(), env.clone())?;
+            },
+            Expr::Symbol(symbol::THREAD_AS) => {
+                let value = args.next().ok_or_else(|| Error::syntax("as-> expects a value"))?;
+                let Some(Expr::Symbol(name)) = args.next() else {
+                    return Err(Error::syntax("as-> expects a name for the threaded value").into());
+                };
+                let local_env = Env::child(&env);
+                result = self.compute(&mut std::iter::once(value).peekable(), env.clone())?;
+                for step in args.by_ref() {
+                    local_env.borrow_mut().variables.insert(*name, result);
+                    result = self.compute(&mut std::iter::once(step).peekable(), local_env.clone())?;
+                }
+            },
+            Expr::Symbol(symbol::LET) => {
+                let local_env = Env::child(&env);
 
-                let bindings_expr = &e[1];
+                let bindings_expr = e.get(1).ok_or_else(|| Error::syntax("let expects a list of bindings"))?;
                 let body_exprs = &e[2..];
-                if let Expr::List(bindings) = bindings_expr {
-                    for binding in bindings {
-                        if let Expr::List(pair) = binding {
-                            if pair.len() != 2 {
-                                panic!("Local declaration should contain variable name and value !");
-                            }
-                            if let Expr::Symbol(name) = &pair[0] {
-                                let value = self.compute(&mut std::iter::once(&pair[1]).peekable(), env.clone());
-                                local_env.clone().borrow_mut().variables.insert(name.to_string(), value);
-                            }
-                        }
+                let Expr::List(bindings, _) = bindings_expr else {
+                    return Err(Error::syntax(format!("let expects a list of bindings, got {}", bindings_expr)).into());
+                };
+                for binding in bindings.iter() {
+                    let Expr::List(pair, _) = binding else {
+                        return Err(Error::syntax(format!("let bindings should be lists, got {}", binding)).into());
+                    };
+                    if pair.len() != 2 {
+                        return Err(Error::syntax("Local declaration should contain variable name and value !").into());
                     }
+                    let Expr::Symbol(name) = &pair[0] else {
+                        return Err(Error::syntax(format!("let binds symbols, got {}", pair[0])).into());
+                    };
+                    let value = self.compute(&mut std::iter::once(&pair[1]).peekable(), env.clone())?;
+                    local_env.clone().borrow_mut().variables.insert(*name, value);
                 }
                 for expr in body_exprs {
-                    let value = self.compute(&mut std::iter::once(expr).peekable(), local_env.clone());
+                    let value = self.compute(&mut std::iter::once(expr).peekable(), local_env.clone())?;
                     result = value;
                 }
             },
-            Expr::Symbol(s) if s == "fn" => {
-                if let (Expr::Symbol(fn_name), Expr::List(fn_args), body_expr) = (&e[1], &e[2], &e[3..]) {
-                    let function_name = fn_name.to_string();
-                    let function_arguments: Vec<String> = fn_args.iter().filter_map(|arg| {
-                        if let Expr::Symbol(name) = arg {
-                            Some(name.clone())
-                        } else {
-                            None
-                        }
-                    }).collect();
-
-                    let mut annotations: Vec<Annotation> = Vec::new();
-                    for expr in body_expr {
-                        if let Expr::List(dir) = expr {
-                            if let Expr::Symbol(name) = &dir[0] {
-                                if name.chars().next() == Some(':') {
-                                    match name.as_str() {
-                                        ":require" =>
-                                            annotations.push(Annotation::Require(dir[1].clone())),
-                                        ":test" =>
-                                            {
-                                                let args = dir[1].clone();
-                                                if let Expr::List(args) = args {
-                                                    annotations.push(
-                                                        Annotation::Test {
-                                                            args,
-                                                            expected: dir[2].clone(),
-                                                        }
-                                                    )
-                                                }
-                                            }
-                                        _ => panic!("Unknown annotation {}", name)
-                                    }
-                                }
-                            }
-                        }
-                    }
-
-                    let function_body = Box::new(body_expr);
-
-                    let captured_env = env.clone();
-                    let function = Value::Function {
-                        params: function_arguments.clone(),
-                        body: Box::new(function_body.last().clone().unwrap().clone()),
-                        func_env: captured_env,
-                        annotations: annotations.clone(),
-                    };
-
-                    env.borrow_mut().variables.insert(function_name.clone(), function);
-
-                    // :test
-                    for annotation in annotations {
-                        if let Annotation::Test { args, expected } = annotation {
-                            let test_env = Rc::new(RefCell::new(Env {
-                                variables: Default::default(),
-                                classes: Default::default(),
-                                parent: Some(env.clone()),
-                            }));
-                            for (arg, value) in function_arguments.iter().zip(args) {
-                                test_env.borrow_mut().variables.insert(arg.clone(), self.compute(&mut std::iter::once(&value).peekable(), env.clone()));
-                            }
-                            let test_result = self.compute(&mut std::iter::once(function_body.last().unwrap()).peekable(), test_env.clone());
-                            let expected_result = self.compute(&mut std::iter::once(&expected).peekable(), test_env.clone());
-                            match (test_result, expected_result) {
-                                (Value::Number(a), Value::Number(b)) if (a - b).abs() < 0.1 => { /* OK */ }
-                                (Value::Bool(a), Value::Bool(b)) if a == b => { /* OK */ }
-                                (Value::String(a), Value::String(b)) if a == b => { /* OK */ }
-                                _ => panic!("Function {} did not pass the test !", function_name)
-                            }
-                        }
-                    }
-                    result = Value::Nil;
-                } else {
-                    panic!("Invalid function definition syntax");
-                }
+            Expr::Symbol(symbol::FN) => {
+                let (function_name, function) = self.make_function(e, env.clone())?;
+                env.borrow_mut().variables.insert(Symbol::from(&function_name), function.clone());
+                self.run_tests(&function_name, &function, env.clone())?;
+                result = Value::Nil;
+            },
+            Expr::Symbol(symbol::DEFMACRO) => {
+                let (macro_name, expander) = self.make_function(e, env.clone())?;
+                env.borrow_mut().macros.insert(Symbol::from(&macro_name), Rc::new(Macro { name: macro_name, expander }));
+            },
+            Expr::Symbol(symbol::QUOTE) => {
+                let form = args.next().ok_or_else(|| Error::syntax("quote expects a form"))?;
+                result = Value::from(form);
             },
-            Expr::Symbol(s) if s == "set" => {
+            Expr::Symbol(symbol::QUASIQUOTE) => {
+                let form = args.next().ok_or_else(|| Error::syntax("quasiquote expects a form"))?;
+                result = self.quasiquote(form, &mut HashMap::new(), env.clone())?;
+            },
+            Expr::Symbol(s @ (symbol::UNQUOTE | symbol::UNQUOTE_SPLICING)) => {
+                return Err(Error::syntax(format!("{} outside of a quasiquote", s)).into());
+            },
+            Expr::Symbol(symbol::SET) => {
                 if let Some(Expr::Symbol(variable)) = args.next() {
-                    let val_opt = {
-                        let ev = env.borrow();
-                        ev.get(variable).map(|v| v.clone())
-                    };
+                    // Only objects are read back, other values are replaced without a copy.
+                    let val_opt = env.borrow().inspect(*variable, |value| match value {
+                        Value::Object { .. } => Some(value.clone()),
+                        _ => None,
+                    });
                     if let Some(value) = val_opt {
                         match value {
-                            Value::Object { class, attrs } => {
-                                if let Some(Expr::String(field)) = args.next() {
-                                    if attrs.contains_key(field) {
-                                        let mut new_attrs = attrs.clone();
-                                        let new_value = self.compute(&mut std::iter::once(args.next().unwrap()).peekable(), env.clone());
-                                        new_attrs.insert(field.to_string(), new_value);
-                                        let new_object = Value::Object {
-                                            class,
-                                            attrs: new_attrs,
-                                        };
-                                        env.borrow_mut().set(&variable, new_object);
-                                    }
+                            Some(Value::Object { class, attrs }) => {
+                                if let Some(Expr::String(field)) = args.next() && attrs.contains_key(field) {
+                                    let mut new_attrs = attrs;
+                                    let new_value = self.compute(&mut std::iter::once(args.next().ok_or_else(|| Error::syntax("set expects a value"))?).peekable(), env.clone())?;
+                                    Rc::make_mut(&mut new_attrs).insert(field.to_string(), new_value);
+                                    let new_object = Value::Object {
+                                        class,
+                                        attrs: new_attrs,
+                                    };
+                                    env.borrow_mut().set(*variable, new_object);
                                 }
                             }
                             _ => {
-                                let new_value = self.compute(&mut std::iter::once(args.next().unwrap()).peekable(), env.clone());
-                                env.borrow_mut().set(&variable, new_value);
+                                let new_value = self.compute(&mut std::iter::once(args.next().ok_or_else(|| Error::syntax("set expects a value"))?).peekable(), env.clone())?;
+                                env.borrow_mut().set(*variable, new_value);
                             }
                         }
                     } else {
-                        panic!("Variable {} not found", variable);
+                        return Err(Error::undefined(format!("Variable {} not found", variable)).into());
                     }
                 }
             },
-            Expr::Symbol(s) if s == "match" => {
+            Expr::Symbol(symbol::MATCH) => {
                 let value_expr_option = args.next();
                 if let Some(value_expr) = value_expr_option {
-                    let value = self.compute(&mut std::iter::once(value_expr).peekable(), env.clone());
+                    let value = self.compute(&mut std::iter::once(value_expr).peekable(), env.clone())?;
                     match value {
                         Value::Array(_arr) => {},
                         Value::Number(n) => {
-                            while let Some(expr) = args.next() {
-                                if let Expr::List(case) = expr {
-                                    let left = case.get(0).unwrap();
-                                    let right = case.get(1).unwrap();
+                            for expr in args.by_ref() {
+                                if let Expr::List(case, _) = expr {
+                                    let left = case.first().ok_or_else(|| Error::syntax("match case expects a pattern"))?;
+                                    let right = case.get(1).ok_or_else(|| Error::syntax("match case expects a result"))?;
                                     match left {
-                                        Expr::Number(l) => {
-                                            if *l == n {
-                                                result = self.compute(&mut std::iter::once(right).peekable(), env.clone());
-                                            }
+                                        Expr::Number(l) if *l == n => {
+                                            result = self.compute(&mut std::iter::once(right).peekable(), env.clone())?;
                                         },
-                                        Expr::List(_cond) => {
-                                            let potential_bool = self.compute(&mut std::iter::once(left).peekable(), env.clone());
-                                            if let Value::Bool(b) = potential_bool {
-                                                if b {
-                                                    result = self.compute(&mut std::iter::once(right).peekable(), env.clone());
-                                                }
+                                        Expr::List(_cond, _) => {
+                                            let potential_bool = self.compute(&mut std::iter::once(left).peekable(), env.clone())?;
+                                            if let Value::Bool(true) = potential_bool {
+                                                result = self.compute(&mut std::iter::once(right).peekable(), env.clone())?;
                                             }
                                         }
                                         _ => {}
@@ -342,73 +755,305 @@ impl Interpreter {
                             }
                         },
                         _ => {
-                            
+
                         }
                     }
                 }
 
             },
             Expr::Symbol(s) => {
-                let class_opt = {
-                    let ev = env.borrow();
-                    ev.class_exists(s)
+                // Looked up in order of precedence, each only when the previous one missed.
+                let macro_opt = env.borrow().macro_exists(*s);
+                let function_opt = match macro_opt {
+                    Some(_) => None,
+                    None => env.borrow().inspect(*s, |value| {
+                        matches!(value, Value::NativeFunction(_) | Value::Function { .. } | Value::ProtocolMethod { .. })
+                            .then(|| value.clone())
+                    }).flatten(),
+                };
+                let class_opt = match (&macro_opt, &function_opt) {
+                    (None, None) => env.borrow().class_exists(*s),
+                    _ => None,
                 };
 
-                if let Some(Value::NativeFunction(f)) = {
-                    let ev = env.borrow();
-                    ev.get(s)
-                } {
-                    let arg_values = args
-                        .into_iter()
-                        .map(|arg| self.compute(&mut std::iter::once(arg).peekable(), env.clone()))
-                        .collect::<Vec<Value>>();
-                    match f {
-                        NativeFunction::Pure(fp) => result = fp(arg_values),
-                        NativeFunction::WithEnv(fwe) => result = fwe(arg_values, env.clone()),
+                if let Some(definition) = macro_opt {
+                    let expanded = self.expand(&definition, &e[1..], span, env.clone())?;
+                    result = self.compute(&mut std::iter::once(&expanded).peekable(), env.clone())?;
+                } else if let Some(function) = function_opt {
+                    let (arg_values, keywords) = self.call_args(args, env.clone())?;
+                    result = self.call_with_keywords(function, arg_values, keywords, env.clone())?;
+                } else if let Some(class) = class_opt {
+                    result = self.instantiate(&class, &e[1..], env.clone())?;
+                } else if let Some((class_name, method_name)) = s.split_once('/') {
+                    let class_opt = env.borrow().class_exists(Symbol::from(class_name));
+                    let class = class_opt.ok_or_else(|| Error::undefined(format!("Undefined class {}", class_name)))?;
+                    let method = class.method(method_name)
+                        .ok_or_else(|| Error::undefined(format!("Undefined method {} for class {}", method_name, class.name)))?;
+                    let (arg_values, keywords) = self.call_args(args, env.clone())?;
+                    result = self.call_with_keywords(method, arg_values, keywords, env.clone())?;
+                } else if e.len() == 1 {
+                    result = self.handle_symbol(*s, env.clone())?;
+                } else {
+                    return Err(Error::undefined(format!("Undefined symbol: {}", s)).into());
+                }
+            }
+            Expr::List(..) => {
+                // A computed head, like `((partial add 1) 2)`, is called when it is a function.
+                let head = self.compute(&mut std::iter::once(&e[0]).peekable(), env.clone())?;
+                match head {
+                    Value::NativeFunction(_) | Value::Function { .. } | Value::ProtocolMethod { .. } => {
+                        let (arg_values, keywords) = self.call_args(args, env.clone())?;
+                        result = self.call_with_keywords(head, arg_values, keywords, env.clone())?;
+                    },
+                    value if e.len() == 1 => result = value,
+                    other => return Err(Error::type_error(format!("{} is not a function", other)).into()),
+                }
+            },
+            _ => return Err(Error::syntax("Invalid function definition syntax").into()),
+        }
+        Ok(result)
+    }
+
+    /// Expands `form` once when it is a macro call, `None` otherwise.
+    pub fn macroexpand_1(&mut self, form: &Expr, env: Rc<RefCell<Env>>) -> Result<Option<Expr>, Signal> {
+        let Expr::List(items, span) = form else {
+            return Ok(None);
+        };
+        let definition = match items.first() {
+            Some(Expr::Symbol(name)) => env.borrow().macro_exists(*name),
+            _ => None,
+        };
+        match definition {
+            Some(definition) => Ok(Some(self.expand(&definition, &items[1..], *span, env)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Expands `form` until its head is no longer a macro, subforms are left as is.
+    pub fn macroexpand(&mut self, form: &Expr, env: Rc<RefCell<Env>>) -> Result<Expr, Signal> {
+        let mut form = form.clone();
+        while let Some(expanded) = self.macroexpand_1(&form, env.clone())? {
+            form = expanded;
+        }
+        Ok(form)
+    }
+
+    /// Expands every macro call in `form`, quoted forms excepted.
+    pub fn macroexpand_all(&mut self, form: &Expr, env: Rc<RefCell<Env>>) -> Result<Expr, Signal> {
+        match self.macroexpand(form, env.clone())? {
+            Expr::List(items, span) if matches!(items.first(), Some(Expr::Symbol(symbol::QUOTE | symbol::QUASIQUOTE))) => {
+                Ok(Expr::List(items, span))
+            },
+            Expr::List(items, span) => Ok(Expr::List(
+                items.iter().map(|item| self.macroexpand_all(item, env.clone())).collect::<Result<_, _>>()?,
+                span,
+            )),
+            Expr::Array(items) => Ok(Expr::Array(
+                items.iter().map(|item| self.macroexpand_all(item, env.clone())).collect::<Result<_, _>>()?,
+            )),
+            other => Ok(other),
+        }
+    }
+
+    /// Expands a whole program without running it. Top level `defmacro` forms are
+    /// evaluated so that the following forms can use them.
+    pub fn expand_program(&mut self, exprs: &[Expr]) -> Result<Vec<Expr>, Signal> {
+        let mut expanded = Vec::new();
+        for expr in exprs {
+            if let Expr::List(items, _) = expr && matches!(items.first(), Some(Expr::Symbol(symbol::DEFMACRO))) {
+                self.compute(&mut std::iter::once(expr).peekable(), self.env.clone())?;
+                expanded.push(expr.clone());
+            } else {
+                expanded.push(self.macroexpand_all(expr, self.env.clone())?);
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Calls a macro expander with its argument forms as data and turns the result
+    /// back into a form located at the macro call.
+    fn expand(&mut self, definition: &Macro, args: &[Expr], span: Span, env: Rc<RefCell<Env>>) -> Result<Expr, Signal> {
+        let forms = args.iter().map(Value::from).collect();
+        let expansion = self.call_value(definition.expander.clone(), forms, env)?;
+        Ok(expansion.to_expr(span)?)
+    }
+
+    /// Quotes `form` except for `~x` and `~@x` parts, which are evaluated. Symbols
+    /// ending with `#` become the same fresh symbol everywhere in the form.
+    fn quasiquote(&mut self, form: &Expr, gensyms: &mut HashMap<Symbol, Symbol>, env: Rc<RefCell<Env>>) -> EvalResult {
+        match form {
+            Expr::List(items, _) if Self::is_unquote(items, symbol::UNQUOTE) => {
+                self.compute(&mut std::iter::once(&items[1]).peekable(), env)
+            },
+            Expr::List(items, _) if Self::is_unquote(items, symbol::UNQUOTE_SPLICING) => {
+                Err(Error::syntax("unquote-splicing outside of a list").into())
+            },
+            Expr::List(items, _) => Ok(Value::List(Rc::new(self.quasiquote_items(items, gensyms, env)?))),
+            Expr::Array(items) => Ok(Value::Array(Rc::new(self.quasiquote_items(items, gensyms, env)?))),
+            Expr::Symbol(name) if name.len() > 1 && name.ends_with('#') => {
+                if !gensyms.contains_key(name) {
+                    let fresh = self.gensym(Symbol::from(&name[..name.len() - 1]));
+                    gensyms.insert(*name, fresh);
+                }
+                Ok(Value::Symbol(gensyms[name]))
+            },
+            other => Ok(Value::from(other)),
+        }
+    }
+
+    fn quasiquote_items(&mut self, items: &[Expr], gensyms: &mut HashMap<Symbol, Symbol>, env: Rc<RefCell<Env>>) -> Result<Vec<Value>, Signal> {
+        let mut values = Vec::new();
+        for item in items {
+            match item {
+                Expr::List(splice, _) if Self::is_unquote(splice, symbol::UNQUOTE_SPLICING) => {
+                    match self.compute(&mut std::iter::once(&splice[1]).peekable(), env.clone())? {
+                        Value::List(spliced) | Value::Array(spliced) => values.extend(spliced.iter().cloned()),
+                        other => return Err(Error::type_error(format!("unquote-splicing expects a list or an array, got {}", other.type_name())).into()),
                     }
-                } else if let Some(Value::Function { params, body, func_env, annotations: _annotation }) = {
-                    let ev = env.borrow();
-                    ev.get(s)
-                } {
-                    let params_value: Vec<_> = e[1..]
-                        .iter()
-                        .map(|p| self.compute(&mut std::iter::once(p).peekable(), env.clone()))
-                        .collect();
-
-                    let local_env = Rc::new(RefCell::new(Env {
-                        variables: HashMap::new(),
-                        classes: Default::default(),
-                        parent: Some(func_env.clone()),
-                    }));
-
-                    for (param, val) in params.iter().zip(params_value) {
-                        local_env.borrow_mut().variables.insert(param.clone(), val);
+                },
+                _ => values.push(self.quasiquote(item, gensyms, env.clone())?),
+            }
+        }
+        Ok(values)
+    }
+
+    fn is_unquote(items: &[Expr], keyword: Symbol) -> bool {
+        matches!(items, [Expr::Symbol(name), _] if *name == keyword)
+    }
+
+    /// Rewrites `(-> x (f a) g)` into `(g (f x a))`, or `(g (f a x))` for `->>`.
+    pub(crate) fn thread<'a>(value: Expr, steps: impl Iterator<Item = &'a Expr>, first: bool, span: Span) -> Expr {
+        steps.fold(value, |threaded, step| match step {
+            Expr::List(call, step_span) if !call.is_empty() => {
+                let mut call = call.to_vec();
+                if first {
+                    call.insert(1, threaded);
+                } else {
+                    call.push(threaded);
+                }
+                Expr::List(call.into(), *step_span)
+            },
+            function => Expr::List(vec![function.clone(), threaded].into(), span),
+        })
+    }
+
+    /// Builds an object from a constructor call, either positional `(Point 1 2)`
+    /// or keyword based `(Point :x 1 :y 2)`. Missing fields take their default value.
+    fn instantiate(&mut self, class: &Class, args: &[Expr], env: Rc<RefCell<Env>>) -> EvalResult {
+        // `(Point p (1 2))` used to bind `p` to the new object. Read as a constructor
+        // call it fails on the unbound name.
+        if let [Expr::Symbol(name), Expr::List(..)] = args
+            && !name.is_keyword()
+            && env.borrow().inspect(*name, |_| ()).is_none() {
+            return Err(Error::syntax(format!("({} {} (...)) no longer binds {}, write (var {} ({} ...)) instead", class.name, name, name, name, class.name)).into());
+        }
+        let mut attrs: HashMap<String, Value> = HashMap::new();
+        let mut args = args.iter();
+        let mut position = 0;
+        while let Some(arg) = args.next() {
+            let field = match arg {
+                Expr::Symbol(keyword) if keyword.is_keyword() => {
+                    let field_name = &keyword[1..];
+                    let field = class.field(field_name)
+                        .ok_or_else(|| Error::undefined(format!("Unknown field {} for {}", field_name, class.name)))?;
+                    if attrs.contains_key(field_name) {
+                        return Err(Error::syntax(format!("Field {} of {} given twice", field_name, class.name)).into());
                     }
+                    let value_expr = args.next()
+                        .ok_or_else(|| Error::syntax(format!("Missing value for field {} of {}", field_name, class.name)))?;
+                    let value = self.compute(&mut std::iter::once(value_expr).peekable(), env.clone())?;
+                    attrs.insert(field.name.clone(), value);
+                    continue;
+                },
+                _ => class.fields.get(position),
+            };
+            if !attrs.is_empty() && attrs.len() != position {
+                return Err(Error::syntax(format!("Positional fields of {} must come before keyword fields", class.name)).into());
+            }
+            let field = field.ok_or_else(|| Error::type_error(format!("{} expects at most {} fields, got {}", class.name, class.fields.len(), position + 1)))?;
+            let value = self.compute(&mut std::iter::once(arg).peekable(), env.clone())?;
+            attrs.insert(field.name.clone(), value);
+            position += 1;
+        }
+        for field in &class.fields {
+            if !attrs.contains_key(&field.name) {
+                let default = field.default.clone()
+                    .ok_or_else(|| Error::type_error(format!("Missing field {} for {}", field.name, class.name)))?;
+                attrs.insert(field.name.clone(), default);
+            }
+        }
+        Ok(Value::Object {
+            class: class.name.clone(),
+            attrs: Rc::new(attrs),
+        })
+    }
 
-                    let mut body_iter = std::iter::once(body.as_ref()).peekable();
-                    result = self.compute(&mut body_iter, local_env);
-                } else if let Some(class) = class_opt {
-                    if let Expr::Symbol(name) = args.next().unwrap() {
-                        if let Expr::List(attrs) = args.next().unwrap() {
-                            let mut hashmap: HashMap<String, Value> = HashMap::new();
-                            for (attr_name, attr_value) in class.clone().iter().zip(attrs) {
-                                let value = self.compute(&mut std::iter::once(attr_value).peekable(), env.clone());
-                                hashmap.insert(attr_name.clone(), value);
-                            }
-                            let mut ev = env.borrow_mut();
-                            ev.variables.insert(name.clone(), Value::Object {
-                                class: s.clone(),
-                                attrs: hashmap,
-                            });
-                        }
+    /// Builds a function value from a `(fn name (params) body)` form, without binding it.
+    fn make_function(&mut self, e: &[Expr], env: Rc<RefCell<Env>>) -> Result<(String, Value), Signal> {
+        if let (Some(Expr::Symbol(fn_name)), Some(Expr::List(fn_args, _)), body_expr) = (e.get(1), e.get(2), e.get(3..).unwrap_or_default()) {
+            let function_name = fn_name.to_string();
+            let params = Params::parse(fn_args)?;
+
+            let mut annotations: Vec<Annotation> = Vec::new();
+            for expr in body_expr {
+                if let Expr::List(dir, _) = expr
+                    && let Some(Expr::Symbol(name)) = dir.first()
+                    && name.is_keyword() {
+                    match name.as_str() {
+                        ":require" => {
+                            let requirement = dir.get(1).ok_or_else(|| Error::syntax(":require expects a condition"))?;
+                            annotations.push(Annotation::Require(requirement.clone()))
+                        },
+                        ":test" => {
+                            let (Some(Expr::List(args, _)), Some(expected)) = (dir.get(1), dir.get(2)) else {
+                                return Err(Error::syntax(":test expects a list of arguments and a result").into());
+                            };
+                            annotations.push(
+                                Annotation::Test {
+                                    args: args.clone(),
+                                    expected: expected.clone(),
+                                }
+                            )
+                        },
+                        _ => return Err(Error::syntax(format!("Unknown annotation {}", name)).into())
+                    }
+                }
+            }
+
+            let function = Value::Function {
+                name: function_name.to_string().into(),
+                params: Rc::new(params),
+                body: Rc::new(body_expr.last().ok_or_else(|| Error::syntax("Function has no body"))?.clone()),
+                func_env: env,
+                annotations: annotations.into(),
+                chunk: Rc::default(),
+            };
+            Ok((function_name, function))
+        } else {
+            Err(Error::syntax("Invalid function definition syntax").into())
+        }
+    }
+
+    /// Runs the `:test` annotations of a freshly defined function.
+    fn run_tests(&mut self, function_name: &str, function: &Value, env: Rc<RefCell<Env>>) -> Result<(), Signal> {
+        if let Value::Function { params, body, annotations, chunk, .. } = function {
+            for annotation in annotations.iter() {
+                if let Annotation::Test { args, expected } = annotation {
+                    let test_env = Env::child(&env);
+                    let (arg_values, keywords) = self.call_args(args.iter(), env.clone())?;
+                    self.bind_params(params, arg_values, keywords, test_env.clone())?;
+                    let test_result = self.call_body(params, body, chunk, test_env.clone())?;
+                    let expected_result = self.compute(&mut std::iter::once(expected).peekable(), test_env.clone())?;
+                    match (test_result, expected_result) {
+                        (Value::Number(a), Value::Number(b)) if (a - b).abs() < 0.1 => { /* OK */ }
+                        (Value::Bool(a), Value::Bool(b)) if a == b => { /* OK */ }
+                        (Value::String(a), Value::String(b)) if a == b => { /* OK */ }
+                        _ => return Err(Error::contract(format!("Function {} did not pass the test !", function_name)).into())
                     }
-                } else {
-                    panic!("Undefined symbol: {}", s);
                 }
             }
-            _ => panic!("Invalid function definition syntax"),
         }
-        result
+        Ok(())
     }
 
     fn handle_number(&mut self, n: f32) -> Value {
@@ -416,31 +1061,20 @@ impl Interpreter {
     }
 
     fn handle_string(&mut self, s: String) -> Value {
-        Value::String(String::from(s))
+        Value::String(s)
     }
 
-    fn handle_array(&mut self, arr: Vec<Expr>, env: Rc<RefCell<Env>>) -> Value {
-        let mut values: Vec<Value> = Vec::new();
+    fn handle_array(&mut self, arr: &[Expr], env: Rc<RefCell<Env>>) -> EvalResult {
+        let mut values: Vec<Value> = Vec::with_capacity(arr.len());
         for a in arr {
-            let mut i = std::iter::once(&a).peekable();
-            let v = self.compute(&mut i, env.clone());
+            let mut i = std::iter::once(a).peekable();
+            let v = self.compute(&mut i, env.clone())?;
             values.push(v);
         }
-        Value::Array(values)
+        Ok(Value::Array(Rc::new(values)))
     }
 
-    fn handle_symbol(&mut self, s: String, env: Rc<RefCell<Env>>) -> Value {
-        let val = {
-            let borrowed_env = env.borrow();
-            borrowed_env.get(&s).map(|v| v.clone())
-        };
-        match val {
-            Some(Value::NativeFunction(f)) => match f {
-                NativeFunction::Pure(fp) => fp(vec![]),
-                NativeFunction::WithEnv(fwe) => fwe(vec![], env.clone()),
-            },
-            Some(v) => v,
-            None => Value::Nil,
-        }
+    fn handle_symbol(&mut self, s: Symbol, env: Rc<RefCell<Env>>) -> EvalResult {
+        env.borrow().get(s).ok_or_else(|| Error::undefined(format!("Undefined symbol: {}", s)).into())
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,5 @@
 use std::str::FromStr;
+use crate::core::expr::Span;
 
 #[derive(Debug)]
 pub enum Token {
@@ -8,48 +9,92 @@ pub enum Token {
     RightParen,
     LeftBracket,
     RightBracket,
-    String(String)
+    String(String),
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
 }
 
 pub struct Lexer;
 
 impl Lexer {
-    pub fn tokenize(input: &str) -> Vec<Token> {
+    pub fn tokenize(input: &str) -> Vec<(Token, Span)> {
         let mut tokens = Vec::new();
         let mut current = String::new();
+        let mut current_span = Span::default();
+        let mut span = Span { line: 1, column: 0 };
         let mut chars = input.chars().peekable();
 
         while let Some(c) = chars.next() {
+            if c == '\n' {
+                span.line += 1;
+                span.column = 0;
+            } else {
+                span.column += 1;
+            }
             if c == '"' {
+                let start = span;
                 let mut string_lit = String::new();
-                while let Some(next_c) = chars.next() {
+                for next_c in chars.by_ref() {
+                    if next_c == '\n' {
+                        span.line += 1;
+                        span.column = 0;
+                    } else {
+                        span.column += 1;
+                    }
                     if next_c == '"' {
                         break;
                     } else {
                         string_lit.push(next_c);
                     }
                 }
-                tokens.push(Token::String(string_lit));
+                tokens.push((Token::String(string_lit), start));
             } else if "()[] \n".contains(c) {
                 if !current.is_empty() {
-                    if current.chars().all(|a| a.is_ascii_digit() || a == '.' || a == '-') {
-                        tokens.push(Token::Number(f32::from_str(&current).unwrap()));
-                    } else {
-                        tokens.push(Token::Identifier(current.clone()));
-                    }
+                    tokens.push((Self::atom(&current), current_span));
                     current.clear();
                 }
                 match c {
-                    '(' => tokens.push(Token::LeftParen),
-                    ')' => tokens.push(Token::RightParen),
-                    '[' => tokens.push(Token::LeftBracket),
-                    ']' => tokens.push(Token::RightBracket),
+                    '(' => tokens.push((Token::LeftParen, span)),
+                    ')' => tokens.push((Token::RightParen, span)),
+                    '[' => tokens.push((Token::LeftBracket, span)),
+                    ']' => tokens.push((Token::RightBracket, span)),
                     _ => {}
                 }
+            } else if current.is_empty() && "'`~".contains(c) {
+                // Reader prefixes, only recognized at the start of a token.
+                match c {
+                    '\'' => tokens.push((Token::Quote, span)),
+                    '`' => tokens.push((Token::Quasiquote, span)),
+                    _ if chars.peek() == Some(&'@') => {
+                        chars.next();
+                        tokens.push((Token::UnquoteSplicing, span));
+                        span.column += 1;
+                    },
+                    _ => tokens.push((Token::Unquote, span)),
+                }
             } else {
+                if current.is_empty() {
+                    current_span = span;
+                }
                 current.push(c);
             }
         }
+        // The input may end right after an atom.
+        if !current.is_empty() {
+            tokens.push((Self::atom(&current), current_span));
+        }
         tokens
     }
-}
\ No newline at end of file
+
+    /// A number when the text reads as one, an identifier otherwise.
+    fn atom(text: &str) -> Token {
+        if text.chars().all(|a| a.is_ascii_digit() || a == '.' || a == '-')
+            && let Ok(n) = f32::from_str(text) {
+            Token::Number(n)
+        } else {
+            Token::Identifier(text.to_string())
+        }
+    }
+}
@@ -0,0 +1,9 @@
+use crate::core::value::Value;
+
+/// A `defmacro` definition. The expander is a function called with the
+/// unevaluated argument forms as data, it returns the form to evaluate instead.
+#[derive(Debug)]
+pub struct Macro {
+    pub name: String,
+    pub expander: Value,
+}
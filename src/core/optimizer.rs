@@ -0,0 +1,344 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use crate::core::expr::{Expr, Span};
+use crate::core::interpreter::Interpreter;
+use crate::core::stdlib::{NativeFunction, Stdlib};
+use crate::core::symbol::{self, Symbol};
+use crate::core::value::Value;
+
+/// Natives that build code at runtime, which may bind any name.
+const RUNTIME_CODE: [&str; 3] = ["eval", "read-string", "symbol"];
+
+/// Forms with arguments that are not evaluated like the ones of a call.
+const OPAQUE: [Symbol; 8] = [
+    symbol::QUOTE, symbol::QUASIQUOTE, symbol::DOT, symbol::STRUCT, symbol::CASE, symbol::PROTOCOL,
+    symbol::IMPL, symbol::DEFMACRO,
+];
+
+/// Optional pass over a parsed program, run before it is executed. It calls
+/// pure builtins on constant arguments ahead of time, drops the `if` and `match`
+/// branches that cannot run and inlines the constants bound by `let`.
+///
+/// A builtin is only folded when the program binds its name nowhere, and a
+/// program that builds code at runtime is left as is.
+pub struct Optimizer {
+    builtins: HashMap<Symbol, Value>,
+    macros: HashSet<Symbol>,
+}
+
+impl Optimizer {
+    pub fn optimize(program: &[Expr]) -> Vec<Expr> {
+        if program.iter().any(|expr| RUNTIME_CODE.iter().any(|name| mentions(expr, Symbol::from(*name)))) {
+            return program.to_vec();
+        }
+        let mut bound = HashSet::new();
+        let mut macros = HashSet::new();
+        for expr in program {
+            bindings(expr, &mut bound, &mut macros);
+        }
+        let builtins = Stdlib::foldable().into_iter()
+            .map(|(name, value)| (Symbol::from(name), value))
+            .filter(|(name, _)| !bound.contains(name))
+            .collect();
+        let optimizer = Optimizer { builtins, macros };
+        program.iter().map(|expr| optimizer.expr(expr)).collect()
+    }
+
+    fn expr(&self, expr: &Expr) -> Expr {
+        match expr {
+            Expr::Array(items) => Expr::Array(items.iter().map(|item| self.expr(item)).collect()),
+            Expr::List(items, span) => self.list(items, *span),
+            other => other.clone(),
+        }
+    }
+
+    fn list(&self, items: &Rc<[Expr]>, span: Span) -> Expr {
+        let Some(Expr::Symbol(head)) = items.first() else {
+            return Expr::List(items.iter().map(|item| self.expr(item)).collect(), span);
+        };
+        let args = &items[1..];
+        match *head {
+            head if head == symbol::QUOTE || head == symbol::QUASIQUOTE || head == symbol::STRUCT || self.macros.contains(&head) => {
+                Expr::List(items.clone(), span)
+            },
+            symbol::THREAD_FIRST | symbol::THREAD_LAST if !args.is_empty() => {
+                let threaded = Interpreter::thread(args[0].clone(), args[1..].iter(), *head == symbol::THREAD_FIRST, span);
+                self.expr(&threaded)
+            },
+            // Only the body, the name, parameters and annotations stay as written.
+            symbol::FN | symbol::DEFMACRO if items.len() > 3 => {
+                let mut items = items.to_vec();
+                let body = items.len() - 1;
+                items[body] = self.expr(&items[body]);
+                Expr::List(items.into(), span)
+            },
+            // Keys are literals.
+            symbol::CASE => {
+                let items = items.iter().enumerate().map(|(index, item)| match item {
+                    Expr::List(clause, clause_span) if index > 1 && !clause.is_empty() => {
+                        let body = clause[1..].iter().map(|expr| self.expr(expr));
+                        Expr::List(std::iter::once(clause[0].clone()).chain(body).collect(), *clause_span)
+                    },
+                    other if index == 1 => self.expr(other),
+                    other => other.clone(),
+                }).collect();
+                Expr::List(items, span)
+            },
+            symbol::COND => Expr::List(items.iter().map(|clause| self.items(clause)).collect(), span),
+            symbol::IF => {
+                let items = self.arguments(items);
+                match items.get(1).and_then(|condition| self.constant(condition)) {
+                    Some(condition) if (3..=4).contains(&items.len()) => match condition.is_truthy() {
+                        true => items[2].clone(),
+                        false => items.get(3).cloned().unwrap_or_else(|| self.nil(span)),
+                    },
+                    _ => Expr::List(items.into(), span),
+                }
+            },
+            // A pattern that is a list is a condition, which must stay a list.
+            symbol::MATCH => {
+                let items = items.iter().enumerate().map(|(index, item)| match item {
+                    Expr::List(case, case_span) if index > 1 && !case.is_empty() => {
+                        let rest = case[1..].iter().map(|expr| self.expr(expr));
+                        Expr::List(std::iter::once(self.items(&case[0])).chain(rest).collect(), *case_span)
+                    },
+                    other if index == 1 => self.expr(other),
+                    other => other.clone(),
+                }).collect();
+                self.match_form(items, span)
+            },
+            symbol::LET => self.let_form(items, span),
+            _ => {
+                let items = self.arguments(items);
+                self.fold(&items).unwrap_or(Expr::List(items.into(), span))
+            },
+        }
+    }
+
+    /// Optimizes every item but the head.
+    fn arguments(&self, items: &[Expr]) -> Vec<Expr> {
+        std::iter::once(items[0].clone()).chain(items[1..].iter().map(|item| self.expr(item))).collect()
+    }
+
+    /// Optimizes the items of a list whose shape matters, keeping it a list.
+    fn items(&self, expr: &Expr) -> Expr {
+        match expr {
+            Expr::List(items, span) => Expr::List(items.iter().map(|item| self.expr(item)).collect(), *span),
+            other => other.clone(),
+        }
+    }
+
+    /// Calls a pure builtin when all its arguments are constants. Calls that fail
+    /// are kept, so that the error is raised at runtime with its location.
+    fn fold(&self, items: &[Expr]) -> Option<Expr> {
+        let Some(Expr::Symbol(head)) = items.first() else {
+            return None;
+        };
+        let Some(Value::NativeFunction(native)) = self.builtins.get(head) else {
+            return None;
+        };
+        let NativeFunction::Pure(function) = native.function else {
+            return None;
+        };
+        let args = items[1..].iter().map(|arg| self.constant(arg)).collect::<Option<Vec<_>>>()?;
+        self.literal(&function(args).ok()?)
+    }
+
+    /// Value of a form that always evaluates to the same value.
+    fn constant(&self, expr: &Expr) -> Option<Value> {
+        match expr {
+            Expr::Number(n) => Some(Value::Number(*n)),
+            Expr::String(s) => Some(Value::String(s.clone())),
+            Expr::Symbol(name) => match self.builtins.get(name) {
+                Some(Value::NativeFunction(_)) | None => None,
+                Some(value) => Some(value.clone()),
+            },
+            Expr::Array(items) => {
+                let items = items.iter().map(|item| self.constant(item)).collect::<Option<Vec<_>>>()?;
+                Some(Value::Array(Rc::new(items)))
+            },
+            Expr::List(items, _) if items.is_empty() => Some(Value::Nil),
+            Expr::List(..) => None,
+        }
+    }
+
+    /// Form evaluating to `value`, for the values that can be written literally.
+    /// Infinities and NaN print as symbols, so they are left to compute.
+    fn literal(&self, value: &Value) -> Option<Expr> {
+        match value {
+            Value::Number(n) if n.is_finite() => Some(Expr::Number(*n)),
+            Value::String(s) => Some(Expr::String(s.clone())),
+            Value::Bool(b) => {
+                let name = if *b { symbol::TRUE } else { symbol::FALSE };
+                self.builtins.contains_key(&name).then_some(Expr::Symbol(name))
+            },
+            Value::Nil => Some(self.nil(Span::default())),
+            Value::Array(items) => Some(Expr::Array(items.iter().map(|item| self.literal(item)).collect::<Option<_>>()?)),
+            _ => None,
+        }
+    }
+
+    /// `nil`, or `()` when the program rebinds that name.
+    fn nil(&self, span: Span) -> Expr {
+        match self.builtins.contains_key(&symbol::NIL) {
+            true => Expr::Symbol(symbol::NIL),
+            false => Expr::List(Rc::new([]), span),
+        }
+    }
+
+    /// `match` only runs the cases of a number equal to its value or whose
+    /// pattern is a condition. Every such case runs and the last one gives the result.
+    fn match_form(&self, items: Vec<Expr>, span: Span) -> Expr {
+        let Some(subject) = items.get(1).and_then(|subject| self.constant(subject)) else {
+            return Expr::List(items.into(), span);
+        };
+        let Value::Number(n) = subject else {
+            return self.nil(span);
+        };
+        let cases = &items[2..];
+        if cases.iter().any(|case| matches!(case, Expr::List(case, _) if case.len() < 2)) {
+            return Expr::List(items.into(), span);
+        }
+        let reachable = |case: &Expr| match case {
+            Expr::List(case, _) => match case[0] {
+                Expr::List(..) => true,
+                Expr::Number(pattern) => pattern == n,
+                _ => false,
+            },
+            _ => false,
+        };
+        let reachable: Vec<&Expr> = cases.iter().filter(|case| reachable(case)).collect();
+        if reachable.iter().any(|case| matches!(case, Expr::List(case, _) if matches!(case[0], Expr::List(..)))) {
+            return Expr::List(items[..2].iter().chain(reachable).cloned().collect(), span);
+        }
+        let results: Vec<Expr> = reachable.iter().filter_map(|case| match case {
+            Expr::List(case, _) => Some(case[1].clone()),
+            _ => None,
+        }).collect();
+        match results.len() {
+            0 => self.nil(span),
+            1 => results[0].clone(),
+            _ => Expr::List(std::iter::once(Expr::Symbol(symbol::DO)).chain(results).collect(), span),
+        }
+    }
+
+    /// Inlines the `let` bindings of constants that the body only reads. The
+    /// scope itself stays, `var` in the body still defines its names in it.
+    fn let_form(&self, items: &[Expr], span: Span) -> Expr {
+        let Some(Expr::List(bindings, bindings_span)) = items.get(1) else {
+            return Expr::List(self.arguments(items).into(), span);
+        };
+        let pairs: Option<Vec<(Symbol, Expr, Span)>> = bindings.iter().map(|binding| match binding {
+            Expr::List(pair, pair_span) => match &pair[..] {
+                [Expr::Symbol(name), value] => Some((*name, self.expr(value), *pair_span)),
+                _ => None,
+            },
+            _ => None,
+        }).collect();
+        let Some(pairs) = pairs else {
+            return Expr::List(self.arguments(items).into(), span);
+        };
+        let mut body = items[2..].to_vec();
+        let mut kept = Vec::new();
+        for (name, value, pair_span) in pairs.iter() {
+            let unique = pairs.iter().filter(|(other, _, _)| other == name).count() == 1;
+            if unique && self.constant(value).is_some() && self.inlinable(*name, &body) {
+                body = body.iter().map(|expr| substitute(expr, *name, value)).collect();
+            } else {
+                kept.push(Expr::List(vec![Expr::Symbol(*name), value.clone()].into(), *pair_span));
+            }
+        }
+        let head = [items[0].clone(), Expr::List(kept.into(), *bindings_span)];
+        Expr::List(head.into_iter().chain(body.iter().map(|expr| self.expr(expr))).collect(), span)
+    }
+
+    /// Whether every use of `name` in `body` is a plain read.
+    fn inlinable(&self, name: Symbol, body: &[Expr]) -> bool {
+        let mut bound = HashSet::new();
+        let mut macros = HashSet::new();
+        for expr in body {
+            bindings(expr, &mut bound, &mut macros);
+        }
+        !bound.contains(&name) && body.iter().all(|expr| self.transparent(expr, name))
+    }
+
+    fn transparent(&self, expr: &Expr, name: Symbol) -> bool {
+        match expr {
+            Expr::List(items, _) => match items.first() {
+                Some(Expr::Symbol(head)) if *head == name => false,
+                Some(Expr::Symbol(head)) if OPAQUE.contains(head) || self.macros.contains(head) => !mentions(expr, name),
+                _ => items.iter().all(|item| self.transparent(item, name)),
+            },
+            Expr::Array(items) => items.iter().all(|item| self.transparent(item, name)),
+            _ => true,
+        }
+    }
+}
+
+fn substitute(expr: &Expr, name: Symbol, value: &Expr) -> Expr {
+    match expr {
+        Expr::Symbol(symbol) if *symbol == name => value.clone(),
+        Expr::List(items, span) => Expr::List(items.iter().map(|item| substitute(item, name, value)).collect(), *span),
+        Expr::Array(items) => Expr::Array(items.iter().map(|item| substitute(item, name, value)).collect()),
+        other => other.clone(),
+    }
+}
+
+fn mentions(expr: &Expr, name: Symbol) -> bool {
+    match expr {
+        Expr::Symbol(symbol) => *symbol == name,
+        Expr::List(items, _) | Expr::Array(items) => items.iter().any(|item| mentions(item, name)),
+        _ => false,
+    }
+}
+
+/// Collects the names `expr` may bind, quoted code included since macros can
+/// return it, and the names of the macros it defines.
+fn bindings(expr: &Expr, bound: &mut HashSet<Symbol>, macros: &mut HashSet<Symbol>) {
+    let items = match expr {
+        Expr::List(items, _) | Expr::Array(items) => items,
+        _ => return,
+    };
+    let mut names = |expr: Option<&Expr>| {
+        if let Some(Expr::List(list, _)) = expr {
+            for item in list.iter() {
+                match item {
+                    Expr::Symbol(name) => { bound.insert(*name); },
+                    Expr::List(pair, _) => if let Some(Expr::Symbol(name)) = pair.first() {
+                        bound.insert(*name);
+                    },
+                    _ => {},
+                }
+            }
+        }
+    };
+    if let (Expr::List(..), Some(Expr::Symbol(head))) = (expr, items.first()) {
+        match *head {
+            symbol::FN | symbol::DEFMACRO => names(items.get(2)),
+            symbol::LET | symbol::FOR => names(items.get(1)),
+            symbol::PROTOCOL => {
+                for signature in &items[1..] {
+                    names(Some(signature));
+                    if let Expr::List(signature, _) = signature {
+                        names(signature.get(1));
+                    }
+                }
+            },
+            _ => {},
+        }
+        let name = match *head {
+            symbol::VAR | symbol::SET | symbol::FN | symbol::DEFMACRO | symbol::CATCH => items.get(1),
+            symbol::THREAD_AS => items.get(2),
+            _ => None,
+        };
+        if let Some(Expr::Symbol(name)) = name {
+            bound.insert(*name);
+            if *head == symbol::DEFMACRO {
+                macros.insert(*name);
+            }
+        }
+    }
+    for item in items.iter() {
+        bindings(item, bound, macros);
+    }
+}
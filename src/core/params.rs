@@ -0,0 +1,76 @@
+use crate::core::error::Error;
+use crate::core::expr::Expr;
+use crate::core::symbol::{self, Symbol};
+use crate::core::value::Value;
+
+/// `:name value` arguments of a call, in call order.
+pub type Keywords = Vec<(Symbol, Value)>;
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: Symbol,
+    pub default: Option<Expr>,
+}
+
+/// Parameter list of a user function, `(a b (c default) & rest)`.
+#[derive(Debug, Clone, Default)]
+pub struct Params {
+    pub positional: Vec<Param>,
+    pub rest: Option<Symbol>,
+}
+
+impl Params {
+    /// Whether both lists bind the same names in the same order, which is all
+    /// a compiled body depends on.
+    pub fn binds_like(&self, other: &Params) -> bool {
+        self.rest == other.rest && self.positional.iter().map(|param| param.name).eq(other.positional.iter().map(|param| param.name))
+    }
+
+    pub fn parse(exprs: &[Expr]) -> Result<Self, Error> {
+        let mut params = Params::default();
+        let mut exprs = exprs.iter();
+        while let Some(expr) = exprs.next() {
+            let param = match expr {
+                Expr::Symbol(symbol::AMPERSAND) => {
+                    match (exprs.next(), exprs.next()) {
+                        (Some(Expr::Symbol(rest)), None) => params.rest = Some(*rest),
+                        _ => return Err(Error::syntax("& expects a single rest parameter at the end")),
+                    }
+                    continue;
+                },
+                Expr::Symbol(name) => Param { name: *name, default: None },
+                Expr::List(pair, _) => match &pair[..] {
+                    [Expr::Symbol(name), default] => Param { name: *name, default: Some(default.clone()) },
+                    _ => return Err(Error::syntax("Optional parameter should contain a name and a default value")),
+                },
+                other => return Err(Error::syntax(format!("Invalid parameter {}", other))),
+            };
+            if param.default.is_none() && params.positional.iter().any(|p| p.default.is_some()) {
+                return Err(Error::syntax(format!("Required parameter {} cannot follow optional ones", param.name)));
+            }
+            if params.get(param.name).is_some() {
+                return Err(Error::syntax(format!("Parameter {} declared twice", param.name)));
+            }
+            params.positional.push(param);
+        }
+        Ok(params)
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<&Param> {
+        self.positional.iter().find(|param| param.name == name)
+    }
+
+    pub fn required(&self) -> usize {
+        self.positional.iter().filter(|param| param.default.is_none()).count()
+    }
+
+    /// Human readable count of accepted arguments, for arity errors.
+    pub fn arity(&self) -> String {
+        let required = self.required();
+        match (&self.rest, self.positional.len()) {
+            (Some(_), _) => format!("at least {}", required),
+            (None, max) if max == required => required.to_string(),
+            (None, max) => format!("{} to {}", required, max),
+        }
+    }
+}
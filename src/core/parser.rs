@@ -1,29 +1,70 @@
 use std::iter::Peekable;
 pub(crate) use crate::core::expr::Expr;
+use crate::core::error::Error;
+use crate::core::expr::Span;
 use crate::core::lexer::Token;
+use crate::core::symbol::{self, Symbol};
 
 pub struct Parser {}
 
 impl Parser {
-    pub fn parse(tokens: &mut Peekable<impl Iterator<Item = Token>>) -> Vec<Expr> {
+    pub fn parse(tokens: &mut Peekable<impl Iterator<Item = (Token, Span)>>) -> Vec<Expr> {
         let mut exprs: Vec<Expr> = Vec::new();
-        while let Some(token) = tokens.next() {
+        while let Some(expr) = Self::parse_one(tokens) {
+            exprs.push(expr);
+        }
+        exprs
+    }
+
+    /// Checks that brackets are balanced, `parse` itself stops quietly at a stray closer.
+    pub fn check(tokens: &[(Token, Span)]) -> Result<(), Error> {
+        let mut open: Vec<(&Token, Span)> = Vec::new();
+        for (token, span) in tokens {
             match token {
-                Token::Identifier(i) => exprs.push(Expr::Symbol(i.to_string())),
-                Token::Number(n) => exprs.push(Expr::Number(n)),
-                Token::String(s) => exprs.push(Expr::String(s)),
-                Token::LeftParen => {
-                    let inner_expr = Self::parse(tokens);
-                    exprs.push(Expr::List(inner_expr));
+                Token::LeftParen | Token::LeftBracket => open.push((token, *span)),
+                Token::RightParen | Token::RightBracket => match (open.pop(), token) {
+                    (Some((Token::LeftParen, _)), Token::RightParen) => {},
+                    (Some((Token::LeftBracket, _)), Token::RightBracket) => {},
+                    _ => return Err(Error::syntax(format!("Unexpected {} at {}", Self::bracket(token), span))),
                 },
-                Token::RightParen => break,
-                Token::LeftBracket => {
-                    let inner_expr = Self::parse(tokens);
-                    exprs.push(Expr::Array(inner_expr));
-                },
-                Token::RightBracket => break,
+                _ => {},
             }
         }
-        exprs
+        match open.pop() {
+            Some((token, span)) => Err(Error::syntax(format!("Unclosed {} at {}", Self::bracket(token), span))),
+            None => Ok(()),
+        }
+    }
+
+    fn bracket(token: &Token) -> &'static str {
+        match token {
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::LeftBracket => "[",
+            _ => "]",
+        }
+    }
+
+    /// Parses the next form, `None` at the end of input or of the enclosing list.
+    fn parse_one(tokens: &mut Peekable<impl Iterator<Item = (Token, Span)>>) -> Option<Expr> {
+        let (token, span) = tokens.next()?;
+        match token {
+            Token::Identifier(i) => Some(Expr::Symbol(Symbol::from(i.as_str()))),
+            Token::Number(n) => Some(Expr::Number(n)),
+            Token::String(s) => Some(Expr::String(s)),
+            Token::LeftParen => Some(Expr::List(Self::parse(tokens).into(), span)),
+            Token::LeftBracket => Some(Expr::Array(Self::parse(tokens).into())),
+            Token::RightParen | Token::RightBracket => None,
+            // `'x` reads as `(quote x)`, and likewise for the other prefixes.
+            Token::Quote => Self::prefixed(symbol::QUOTE, span, tokens),
+            Token::Quasiquote => Self::prefixed(symbol::QUASIQUOTE, span, tokens),
+            Token::Unquote => Self::prefixed(symbol::UNQUOTE, span, tokens),
+            Token::UnquoteSplicing => Self::prefixed(symbol::UNQUOTE_SPLICING, span, tokens),
+        }
+    }
+
+    fn prefixed(name: Symbol, span: Span, tokens: &mut Peekable<impl Iterator<Item = (Token, Span)>>) -> Option<Expr> {
+        let expr = Self::parse_one(tokens)?;
+        Some(Expr::List(vec![Expr::Symbol(name), expr].into(), span))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,30 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::core::value::Value;
+
+/// A named set of method signatures that can be implemented for any runtime type.
+#[derive(Debug)]
+pub struct Protocol {
+    pub name: String,
+    pub methods: Vec<(String, Vec<String>)>,
+    pub impls: RefCell<HashMap<String, HashMap<String, Value>>>,
+}
+
+impl Protocol {
+    pub fn new(name: &str, methods: Vec<(String, Vec<String>)>) -> Self {
+        Protocol {
+            name: name.to_string(),
+            methods,
+            impls: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn signature(&self, method: &str) -> Option<&Vec<String>> {
+        self.methods.iter().find(|(name, _)| name == method).map(|(_, params)| params)
+    }
+
+    /// Finds the implementation of `method` for the given runtime type name.
+    pub fn dispatch(&self, type_name: &str, method: &str) -> Option<Value> {
+        self.impls.borrow().get(type_name).and_then(|methods| methods.get(method).cloned())
+    }
+}
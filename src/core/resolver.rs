@@ -0,0 +1,62 @@
+use crate::core::params::Params;
+use crate::core::symbol::Symbol;
+
+/// Position of a local variable: `depth` scopes above the current one, in the
+/// slot `slot` of that scope's `Variables`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Address {
+    pub depth: usize,
+    pub slot: usize,
+}
+
+/// Where the compiler expects a variable: a resolved local, or a free variable
+/// to look up by name above the `outer` scopes opened by the compiled code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Local(Address),
+    Free { outer: usize },
+}
+
+/// Scopes opened while compiling, innermost last, with their names in slot order.
+///
+/// Only scopes created by the compiled code are known. Names of the global scope
+/// and of the environment a function closes over are free: they are looked up by
+/// name at runtime.
+#[derive(Debug, Default)]
+pub struct Resolver {
+    scopes: Vec<Vec<Symbol>>,
+}
+
+impl Resolver {
+    /// Resolver for a function body, whose parameters are bound by `Interpreter::bind_params`.
+    pub fn function(params: &Params) -> Self {
+        let mut names: Vec<Symbol> = params.positional.iter().map(|param| param.name).collect();
+        names.extend(params.rest);
+        Resolver { scopes: vec![names] }
+    }
+
+    pub fn push(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    pub fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Declares a name in the innermost scope. Nothing is declared in the global scope.
+    pub fn declare(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() && !scope.contains(&name) {
+            scope.push(name);
+        }
+    }
+
+    pub fn resolve(&self, name: Symbol) -> Binding {
+        let local = self.scopes.iter().rev().enumerate().find_map(|(depth, scope)| {
+            scope.iter().position(|&known| known == name).map(|slot| Address { depth, slot })
+        });
+        match local {
+            Some(address) => Binding::Local(address),
+            None => Binding::Free { outer: self.scopes.len() },
+        }
+    }
+}
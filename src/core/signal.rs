@@ -0,0 +1,21 @@
+use crate::core::error::Error;
+use crate::core::value::Value;
+
+/// Non-local exits raised while evaluating an expression. They unwind through
+/// `do`, `let`, `if` and `match` until a loop, a function call or a `try` handles them.
+#[derive(Debug, Clone)]
+pub enum Signal {
+    Break(Value),
+    Continue,
+    Return(Value),
+    Throw(Value),
+    Error(Error),
+}
+
+impl From<Error> for Signal {
+    fn from(error: Error) -> Self {
+        Signal::Error(error)
+    }
+}
+
+pub type EvalResult = Result<Value, Signal>;
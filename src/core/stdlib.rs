@@ -1,14 +1,48 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 use crate::core::env::Env;
+use crate::core::error::Error;
+use crate::core::expr::{Expr, Span};
+use crate::core::heap;
+use crate::core::lexer::Lexer;
+use crate::core::parser::Parser;
+use crate::core::interpreter::Interpreter;
+use crate::core::signal::{EvalResult, Signal};
+use crate::core::symbol::{self, Symbol};
 use crate::core::value::Value;
 use rand::prelude::*;
 
-#[derive(Debug, Clone)]
+pub type NativeResult = Result<Value, Error>;
+
+/// Natives built at runtime that capture values, like the result of `partial`.
+pub type ClosureFn = dyn Fn(&mut Interpreter, Vec<Value>, Rc<RefCell<Env>>) -> EvalResult;
+
+#[derive(Clone)]
 pub enum NativeFunction {
-    Pure(fn(Vec<Value>) -> Value),
-    WithEnv(fn(Vec<Value>, Rc<RefCell<Env>>) -> Value),
+    Pure(fn(Vec<Value>) -> NativeResult),
+    WithEnv(fn(Vec<Value>, Rc<RefCell<Env>>) -> NativeResult),
+    WithInterpreter(fn(&mut Interpreter, Vec<Value>, Rc<RefCell<Env>>) -> EvalResult),
+    Closure(Rc<ClosureFn>),
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NativeFunction::Pure(_) => write!(f, "Pure"),
+            NativeFunction::WithEnv(_) => write!(f, "WithEnv"),
+            NativeFunction::WithInterpreter(_) => write!(f, "WithInterpreter"),
+            NativeFunction::Closure(_) => write!(f, "Closure"),
+        }
+    }
+}
+
+/// A native function with the name it was registered under.
+#[derive(Debug)]
+pub struct Native {
+    pub name: String,
+    pub function: NativeFunction,
 }
 
 pub struct Stdlib;
@@ -17,301 +51,881 @@ impl Stdlib {
     pub fn builtins() -> HashMap<String, Value> {
         let mut map = HashMap::new();
         map.extend(Stdlib::math_symbol()); // -> + - * / ^ > >= < <=
-        map.extend(Stdlib::logical_symbol()); // -> && || != ==
+        map.extend(Stdlib::math_functions()); // -> % quot min max sqrt sin cos log exp clamp sign pi e bit-and ...
+        map.extend(Stdlib::logical_symbol()); // -> true false nil && || != == not
         map.extend(Stdlib::io_functions()); // -> print read
         map.extend(Stdlib::language_functions()); // -> typeof get set
         map.extend(Stdlib::array_functions()); // -> len
+        map.extend(Stdlib::object_functions()); // -> assoc update get-in assoc-in update-in
+        map.extend(Stdlib::reference_functions()); // -> ref deref reset! swap!
+        map.extend(Stdlib::functional_functions()); // -> apply partial compose pipe identity constantly arity params name annotations
+        map.extend(Stdlib::result_functions()); // -> ok err ok? err? unwrap unwrap-or map-err
+        map.extend(Stdlib::code_functions()); // -> gensym symbol list macroexpand-1 macroexpand read-string eval current-env
         map.extend(Stdlib::string_functions()); // -> parse
         map.extend(Stdlib::random_functions()); // rand
+        map.extend(Stdlib::memory_functions()); // gc memory
+        map
+    }
+
+    /// Builtins without side effects, whose result only depends on their
+    /// arguments: the optimizer may call them ahead of time.
+    pub fn foldable() -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        map.extend(Stdlib::math_symbol());
+        map.extend(Stdlib::math_functions());
+        map.extend(Stdlib::logical_symbol());
+        map.extend(Stdlib::language_functions().into_iter().filter(|(name, _)| name == "typeof"));
+        map.extend(Stdlib::array_functions().into_iter().filter(|(name, _)| name == "len" || name == "concat"));
         map
     }
 
     fn math_symbol() -> HashMap<String, Value> {
         let mut map = HashMap::new();
-        map.insert("+".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let (Some(Value::Number(a)), Some(Value::Number(b))) = (args.get(0), args.get(1)) {
-                Value::Number(a + b)
-            } else {
-                panic!("< + > only apply on two numbers !")
-            }
+        map.insert("+".to_string(), Value::native("+", NativeFunction::Pure(|args| {
+            fold_numbers("+", &args, 0.0, |a, b| a + b)
         })));
-        map.insert("-".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let (Some(Value::Number(a)), Some(Value::Number(b))) = (args.get(0), args.get(1)) {
-                Value::Number(a - b)
-            } else {
-                panic!("< - > only apply on two numbers !")
-            }
+        map.insert("-".to_string(), Value::native("-", NativeFunction::Pure(|args| {
+            fold_inverse("-", &args, 0.0, |a, b| a - b)
         })));
-        map.insert("*".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let (Some(Value::Number(a)), Some(Value::Number(b))) = (args.get(0), args.get(1)) {
-                Value::Number(a * b)
-            } else {
-                panic!("< * > only apply on two numbers !")
-            }
+        map.insert("*".to_string(), Value::native("*", NativeFunction::Pure(|args| {
+            fold_numbers("*", &args, 1.0, |a, b| a * b)
         })));
-        map.insert("/".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let (Some(Value::Number(a)), Some(Value::Number(b))) = (args.get(0), args.get(1)) {
-                Value::Number(a / b)
-            } else {
-                panic!("< / > only apply on two numbers !")
-            }
+        map.insert("/".to_string(), Value::native("/", NativeFunction::Pure(|args| {
+            fold_inverse("/", &args, 1.0, |a, b| a / b)
         })));
-        map.insert("^".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let (Some(Value::Number(a)), Some(Value::Number(b))) = (args.get(0), args.get(1)) {
-                Value::Number(a**b)
+        map.insert("^".to_string(), Value::native("^", NativeFunction::Pure(|args| {
+            if let (Some(Value::Number(a)), Some(Value::Number(b))) = (args.first(), args.get(1)) {
+                Ok(Value::Number(a.powf(*b)))
             } else {
-                panic!("< ^ > only apply on two numbers !")
+                Err(Error::type_error("< ^ > only apply on two numbers !"))
             }
         })));
-        map.insert(">".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let (Some(Value::Number(a)), Some(Value::Number(b))) = (args.get(0), args.get(1)) {
-                Value::Bool(a > b)
-            } else {
-                panic!("> only apply on two numbers !")
-            }
+        map.insert(">".to_string(), Value::native(">", NativeFunction::Pure(|args| {
+            compare_numbers(">", &args, |a, b| a > b)
         })));
-        map.insert("<".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let (Some(Value::Number(a)), Some(Value::Number(b))) = (args.get(0), args.get(1)) {
-                Value::Bool(a < b)
-            } else {
-                panic!("< only apply on two numbers !")
-            }
+        map.insert("<".to_string(), Value::native("<", NativeFunction::Pure(|args| {
+            compare_numbers("<", &args, |a, b| a < b)
         })));
-        map.insert(">=".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let (Some(Value::Number(a)), Some(Value::Number(b))) = (args.get(0), args.get(1)) {
-                Value::Bool(a >= b)
+        map.insert(">=".to_string(), Value::native(">=", NativeFunction::Pure(|args| {
+            compare_numbers(">=", &args, |a, b| a >= b)
+        })));
+        map.insert("<=".to_string(), Value::native("<=", NativeFunction::Pure(|args| {
+            compare_numbers("<=", &args, |a, b| a <= b)
+        })));
+        map.insert("abs".to_string(), Value::native("abs", NativeFunction::Pure(|args| {
+            if let Some(Value::Number(n)) = args.first() {
+                Ok(Value::Number(n.abs()))
             } else {
-                panic!(">= only apply on two numbers !")
+                Err(Error::type_error("abs expects a number"))
             }
         })));
-        map.insert("<=".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let (Some(Value::Number(a)), Some(Value::Number(b))) = (args.get(0), args.get(1)) {
-                Value::Bool(a <= b)
+        map.insert("floor".to_string(), Value::native("floor", NativeFunction::Pure(|args| {
+            if let Some(Value::Number(n)) = args.first() {
+                Ok(Value::Number(n.floor()))
             } else {
-                panic!("<= only apply on two numbers !")
+                Ok(Value::Nil)
             }
         })));
-        map.insert("abs".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let Some(Value::Number(n)) = args.get(0) {
-                Value::Number(n.abs())
+        map.insert("ceil".to_string(), Value::native("ceil", NativeFunction::Pure(|args| {
+            if let Some(Value::Number(n)) = args.first() {
+                Ok(Value::Number(n.ceil()))
             } else {
-                panic!("abs expects a number")
+                Ok(Value::Nil)
             }
         })));
-        map.insert("floor".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let Some(Value::Number(n)) = args.get(0) {
-                Value::Number(n.floor())
+        map.insert("round".to_string(), Value::native("round", NativeFunction::Pure(|args| {
+            if let Some(Value::Number(n)) = args.first() {
+                Ok(Value::Number(n.round()))
             } else {
-                Value::Nil
+                Ok(Value::Nil)
             }
         })));
-        map.insert("ceil".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let Some(Value::Number(n)) = args.get(0) {
-                Value::Number(n.ceil())
+        map.insert("inc".to_string(), Value::native("inc", NativeFunction::Pure(|args| {
+            if let Some(Value::Number(n)) = args.first() {
+                Ok(Value::Number(n+1.0))
             } else {
-                Value::Nil
+                Ok(Value::Nil)
             }
         })));
-        map.insert("round".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let Some(Value::Number(n)) = args.get(0) {
-                Value::Number(n.round())
+        map.insert("dec".to_string(), Value::native("dec", NativeFunction::Pure(|args| {
+            if let Some(Value::Number(n)) = args.first() {
+                Ok(Value::Number(n-1.0))
             } else {
-                Value::Nil
+                Ok(Value::Nil)
             }
         })));
-        map.insert("inc".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let Some(Value::Number(n)) = args.get(0) {
-                Value::Number(n+1.0)
-            } else {
-                Value::Nil
+        map
+    }
+    /// Math functions follow IEEE 754 floats: `(/ 1 0)` is `inf`, `(sqrt -1)` and
+    /// `(% 1 0)` are `nan`, and `nan` is not equal to anything, itself included.
+    /// `min`, `max` and `clamp` give `nan` when an argument is `nan`.
+    /// Bitwise operations work on the integer value of their arguments and reject
+    /// fractional, `nan` or infinite numbers.
+    fn math_functions() -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        map.insert("pi".to_string(), Value::Number(std::f32::consts::PI));
+        map.insert("e".to_string(), Value::Number(std::f32::consts::E));
+        map.insert("inf".to_string(), Value::Number(f32::INFINITY));
+        map.insert("nan".to_string(), Value::Number(f32::NAN));
+        map.insert("%".to_string(), Value::native("%", NativeFunction::Pure(|args| {
+            // Remainder takes the sign of the dividend, `(% -7 3)` is -1.
+            let [a, b] = binary_numbers("%", &args)?;
+            Ok(Value::Number(a % b))
+        })));
+        map.insert("quot".to_string(), Value::native("quot", NativeFunction::Pure(|args| {
+            // Integer division truncates toward zero, dividing by zero gives `inf` or `nan`.
+            let [a, b] = binary_numbers("quot", &args)?;
+            Ok(Value::Number((a / b).trunc()))
+        })));
+        map.insert("min".to_string(), Value::native("min", NativeFunction::Pure(|args| {
+            fold_inverse("min", &args, f32::INFINITY, minimum)
+        })));
+        map.insert("max".to_string(), Value::native("max", NativeFunction::Pure(|args| {
+            fold_inverse("max", &args, f32::NEG_INFINITY, maximum)
+        })));
+        map.insert("clamp".to_string(), Value::native("clamp", NativeFunction::Pure(|args| {
+            match numbers("clamp", &args)?[..] {
+                [x, low, high] if low <= high => Ok(Value::Number(x.clamp(low, high))),
+                [_, _, _] => Err(Error::type_error("clamp expects its lower bound to be below its upper bound")),
+                _ => Err(Error::type_error("clamp expects a number and two bounds")),
             }
         })));
-        map.insert("dec".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let Some(Value::Number(n)) = args.get(0) {
-                Value::Number(n-1.0)
-            } else {
-                Value::Nil
+        map.insert("sign".to_string(), Value::native("sign", NativeFunction::Pure(|args| {
+            // Unlike `f32::signum`, zero has sign 0 and `nan` stays `nan`.
+            let x = unary_number("sign", &args)?;
+            Ok(Value::Number(if x == 0.0 || x.is_nan() { x } else { x.signum() }))
+        })));
+        map.insert("atan2".to_string(), Value::native("atan2", NativeFunction::Pure(|args| {
+            let [y, x] = binary_numbers("atan2", &args)?;
+            Ok(Value::Number(y.atan2(x)))
+        })));
+        map.insert("log".to_string(), Value::native("log", NativeFunction::Pure(|args| {
+            // Natural logarithm, or in the base given as second argument.
+            match numbers("log", &args)?[..] {
+                [x] => Ok(Value::Number(x.ln())),
+                [x, base] => Ok(Value::Number(x.log(base))),
+                _ => Err(Error::type_error("log expects a number and an optional base")),
             }
         })));
+        map.insert("sqrt".to_string(), Value::native("sqrt", NativeFunction::Pure(|args| {
+            Ok(Value::Number(unary_number("sqrt", &args)?.sqrt()))
+        })));
+        map.insert("exp".to_string(), Value::native("exp", NativeFunction::Pure(|args| {
+            Ok(Value::Number(unary_number("exp", &args)?.exp()))
+        })));
+        map.insert("sin".to_string(), Value::native("sin", NativeFunction::Pure(|args| {
+            Ok(Value::Number(unary_number("sin", &args)?.sin()))
+        })));
+        map.insert("cos".to_string(), Value::native("cos", NativeFunction::Pure(|args| {
+            Ok(Value::Number(unary_number("cos", &args)?.cos()))
+        })));
+        map.insert("tan".to_string(), Value::native("tan", NativeFunction::Pure(|args| {
+            Ok(Value::Number(unary_number("tan", &args)?.tan()))
+        })));
+        map.insert("asin".to_string(), Value::native("asin", NativeFunction::Pure(|args| {
+            Ok(Value::Number(unary_number("asin", &args)?.asin()))
+        })));
+        map.insert("acos".to_string(), Value::native("acos", NativeFunction::Pure(|args| {
+            Ok(Value::Number(unary_number("acos", &args)?.acos()))
+        })));
+        map.insert("atan".to_string(), Value::native("atan", NativeFunction::Pure(|args| {
+            Ok(Value::Number(unary_number("atan", &args)?.atan()))
+        })));
+        map.insert("trunc".to_string(), Value::native("trunc", NativeFunction::Pure(|args| {
+            Ok(Value::Number(unary_number("trunc", &args)?.trunc()))
+        })));
+        map.insert("nan?".to_string(), Value::native("nan?", NativeFunction::Pure(|args| {
+            Ok(Value::Bool(unary_number("nan?", &args)?.is_nan()))
+        })));
+        map.insert("infinite?".to_string(), Value::native("infinite?", NativeFunction::Pure(|args| {
+            Ok(Value::Bool(unary_number("infinite?", &args)?.is_infinite()))
+        })));
+        map.insert("bit-and".to_string(), Value::native("bit-and", NativeFunction::Pure(|args| {
+            let [a, b] = binary_integers("bit-and", &args)?;
+            Ok(Value::Number((a & b) as f32))
+        })));
+        map.insert("bit-or".to_string(), Value::native("bit-or", NativeFunction::Pure(|args| {
+            let [a, b] = binary_integers("bit-or", &args)?;
+            Ok(Value::Number((a | b) as f32))
+        })));
+        map.insert("bit-xor".to_string(), Value::native("bit-xor", NativeFunction::Pure(|args| {
+            let [a, b] = binary_integers("bit-xor", &args)?;
+            Ok(Value::Number((a ^ b) as f32))
+        })));
+        map.insert("bit-not".to_string(), Value::native("bit-not", NativeFunction::Pure(|args| {
+            let a = integer("bit-not", unary_number("bit-not", &args)?)?;
+            Ok(Value::Number(!a as f32))
+        })));
+        map.insert("bit-shl".to_string(), Value::native("bit-shl", NativeFunction::Pure(|args| {
+            let [a, b] = binary_integers("bit-shl", &args)?;
+            let shift = u32::try_from(b).ok().filter(|b| *b < 64)
+                .ok_or_else(|| Error::type_error("bit-shl expects a shift between 0 and 63"))?;
+            Ok(Value::Number((a << shift) as f32))
+        })));
+        map.insert("bit-shr".to_string(), Value::native("bit-shr", NativeFunction::Pure(|args| {
+            let [a, b] = binary_integers("bit-shr", &args)?;
+            let shift = u32::try_from(b).ok().filter(|b| *b < 64)
+                .ok_or_else(|| Error::type_error("bit-shr expects a shift between 0 and 63"))?;
+            Ok(Value::Number((a >> shift) as f32))
+        })));
         map
     }
     fn logical_symbol() -> HashMap<String, Value> {
         let mut map = HashMap::new();
-        map.insert("&&".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-                if let (Some(Value::Bool(a)), Some(Value::Bool(b))) = (args.get(0), args.get(1)) {
-                    Value::Bool(*a && *b)
+        map.insert("true".to_string(), Value::Bool(true));
+        map.insert("false".to_string(), Value::Bool(false));
+        map.insert("nil".to_string(), Value::Nil);
+        map.insert("&&".to_string(), Value::native("&&", NativeFunction::Pure(|args| {
+                if let (Some(Value::Bool(a)), Some(Value::Bool(b))) = (args.first(), args.get(1)) {
+                    Ok(Value::Bool(*a && *b))
                 } else {
-                    panic!("< && > only apply on two booleans !")
+                    Err(Error::type_error("< && > only apply on two booleans !"))
                 }
             }
         )));
-        map.insert("||".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-                if let (Some(Value::Bool(a)), Some(Value::Bool(b))) = (args.get(0), args.get(1)) {
-                    Value::Bool(*a || *b)
+        map.insert("||".to_string(), Value::native("||", NativeFunction::Pure(|args| {
+                if let (Some(Value::Bool(a)), Some(Value::Bool(b))) = (args.first(), args.get(1)) {
+                    Ok(Value::Bool(*a || *b))
                 } else {
-                    panic!("< || > only apply on two booleans !")
+                    Err(Error::type_error("< || > only apply on two booleans !"))
                 }
             }
         )));
-        map.insert("==".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-                match (args.get(0), args.get(1)) {
-                    (Some(Value::Bool(a)), Some(Value::Bool(b))) => Value::Bool(*a == *b),
-                    (Some(Value::Number(a)), Some(Value::Number(b))) => Value::Bool(a == b),
-                    (Some(Value::String(a)), Some(Value::String(b))) => Value::Bool(*a == *b),
-                    _ => panic!("< == > misses parameters !")
+        map.insert("==".to_string(), Value::native("==", NativeFunction::Pure(|args| {
+            if args.len() < 2 {
+                return Err(Error::type_error("< == > misses parameters !"));
+            }
+            for pair in args.windows(2) {
+                if !equal("==", &pair[0], &pair[1])? {
+                    return Ok(Value::Bool(false));
                 }
             }
-        )));
-        map.insert("!=".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            match (args.get(0), args.get(1)) {
-                (Some(Value::Bool(a)), Some(Value::Bool(b))) => Value::Bool(*a != *b),
-                (Some(Value::Number(a)), Some(Value::Number(b))) => Value::Bool(a != b),
-                (Some(Value::String(a)), Some(Value::String(b))) => Value::Bool(*a != *b),
-                _ => panic!("< != > misses parameters !")
+            Ok(Value::Bool(true))
+        })));
+        map.insert("!=".to_string(), Value::native("!=", NativeFunction::Pure(|args| {
+            match &args[..] {
+                [a, b] => Ok(Value::Bool(!equal("!=", a, b)?)),
+                _ => Err(Error::type_error("< != > expects two arguments")),
             }
         })));
-        map.insert("not".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let Some(Value::Bool(b)) = args.get(0) {
-                Value::Bool(!b)
-            } else {
-                panic!("not expects a boolean")
+        map.insert("not".to_string(), Value::native("not", NativeFunction::Pure(|args| {
+            match args.first() {
+                Some(value) => Ok(Value::Bool(!value.is_truthy())),
+                None => Err(Error::type_error("not misses parameters !"))
             }
         })));
         map
     }
     fn io_functions() -> HashMap<String, Value> {
         let mut map = HashMap::new();
-        map.insert("print".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
+        map.insert("print".to_string(), Value::native("print", NativeFunction::Pure(|args| {
             for val in args {
-                print!("{} ", val.to_string());
+                print!("{} ", val);
             }
             println!();
-            Value::Nil
+            Ok(Value::Nil)
         })));
-        map.insert("read".to_string(), Value::NativeFunction(NativeFunction::Pure(|_| {
-            let mut line = String::new();
-            let _ = std::io::stdin().read_line(&mut line).unwrap();
-            Value::String(line.trim_end().to_string())
+        map.insert("read".to_string(), Value::native("read", NativeFunction::Pure(|_| {
+            Ok(read_line())
         })));
-        map.insert("ask".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let Some(Value::String(prompt)) = args.get(0) {
+        map.insert("ask".to_string(), Value::native("ask", NativeFunction::Pure(|args| {
+            if let Some(Value::String(prompt)) = args.first() {
                 print!("{}", prompt);
                 use std::io::{self, Write};
                 io::stdout().flush().unwrap();
             }
 
-            let mut line = String::new();
-            let _ = std::io::stdin().read_line(&mut line).unwrap();
-            Value::String(line.trim_end().to_string())
+            Ok(read_line())
         })));
         map
     }
     fn language_functions() -> HashMap<String, Value> {
         let mut map = HashMap::new();
-        map.insert("typeof".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            return match args.first() {
-                Some(Value::Number(_)) => Value::String("Number".to_string()),
-                Some(Value::String(_)) => Value::String("String".to_string()),
-                Some(Value::Array(_)) => Value::String("Array".to_string()),
-                Some(Value::Function { params: _, body: _, func_env: _, annotations: _ }) => Value::String("Function".to_string()),
-                Some(Value::NativeFunction(_)) => Value::String("Function".to_string()),
-                Some(Value::Bool(_)) => Value::String("Bool".to_string()),
-                _ => panic!("typeof misses parameters !")
-            }
-        })));
-        map.insert("get".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            match args.get(0) {
-                Some(Value::Array(arr)) => {
+        map.insert("typeof".to_string(), Value::native("typeof", NativeFunction::Pure(|args| {
+            match args.first() {
+                Some(value) => Ok(Value::String(value.type_name())),
+                None => Err(Error::type_error("typeof misses parameters !"))
+            }
+        })));
+        map.insert("get".to_string(), Value::native("get", NativeFunction::Pure(|args| {
+            match args.first() {
+                Some(Value::Array(arr) | Value::List(arr)) => {
                     let index = match args.get(1) {
-                        Some(Value::Number(n)) => *n as usize,
-                        _ => panic!("Second argument to get must be a number (index)"),
+                        Some(Value::Number(n)) => *n,
+                        _ => return Err(Error::type_error("Second argument to get must be a number (index)")),
                     };
-                    arr.get(index).cloned().unwrap_or(Value::Nil)
+                    match arr.get(index as usize) {
+                        Some(value) if index >= 0.0 => Ok(Value::Ok(Box::new(value.clone()))),
+                        _ => Ok(failure(format!("Index {} out of bounds for array of length {}", index, arr.len()))),
+                    }
                 },
                 Some(Value::String(s)) => {
                     let index = match args.get(1) {
-                        Some(Value::Number(n)) => *n as usize,
-                        _ => panic!("Second argument to get must be a number (index)"),
+                        Some(Value::Number(n)) => *n,
+                        _ => return Err(Error::type_error("Second argument to get must be a number (index)")),
                     };
-                    s.chars().nth(index)
-                        .map(|c| Value::String(c.to_string()))
-                        .unwrap_or(Value::Nil)
+                    match s.chars().nth(index as usize) {
+                        Some(c) if index >= 0.0 => Ok(Value::Ok(Box::new(Value::String(c.to_string())))),
+                        _ => Ok(failure(format!("Index {} out of bounds for string of length {}", index, s.chars().count()))),
+                    }
                 },
-                Some(Value::Object{ class: _class, attrs}) => {
+                Some(Value::Object{ class, attrs}) => {
                     let key = args.get(1).unwrap_or(&Value::Nil);
                     if let Value::String(s) = key {
-                        if attrs.contains_key(s) {
-                            attrs.get(s).unwrap().clone()
-                        } else {
-                            Value::Nil
+                        match attrs.get(s) {
+                            Some(value) => Ok(Value::Ok(Box::new(value.clone()))),
+                            None => Ok(failure(format!("No field {} in {}", s, class))),
                         }
                     } else {
-                        Value::Nil
+                        Err(Error::type_error("Second argument to get must be a string (field)"))
                     }
                 },
-                _ => panic!("get only supports arrays and strings"),
+                _ => Err(Error::type_error("get only supports arrays and strings")),
             }
         })));
         map
     }
     fn array_functions() -> HashMap<String, Value> {
         let mut map = HashMap::new();
-        map.insert("len".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            return match args.get(0) {
-                Some(Value::Array(arr)) => Value::Number(arr.len() as f32),
-                Some(Value::String(s)) => Value::Number(s.chars().count() as f32),
-                _ => panic!("Type has no length !")
+        map.insert("len".to_string(), Value::native("len", NativeFunction::Pure(|args| {
+            match args.first() {
+                Some(Value::Array(arr) | Value::List(arr)) => Ok(Value::Number(arr.len() as f32)),
+                Some(Value::String(s)) => Ok(Value::Number(s.chars().count() as f32)),
+                _ => Err(Error::type_error("Type has no length !"))
             }
         })));
-        map.insert("concat".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if args.len() == 0 {
-                return Value::Nil;
+        map.insert("concat".to_string(), Value::native("concat", NativeFunction::Pure(|args| {
+            if args.is_empty() {
+                return Ok(Value::Nil);
             }
-            return match args[0] {
+            match args[0] {
                 Value::String(_) => {
                     let mut result = String::new();
                     for arg in args.iter() {
                         result.push_str(&arg.to_string());
                     }
-                    Value::String(result)
+                    Ok(Value::String(result))
                 }
-                _ => Value::Nil,
+                _ => Ok(Value::Nil),
             }
         })));
-        map.insert("range".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            if let (Some(Value::Number(start)), Some(Value::Number(end))) = (args.get(0), args.get(1)) {
+        map.insert("range".to_string(), Value::native("range", NativeFunction::Pure(|args| {
+            if let (Some(Value::Number(start)), Some(Value::Number(end))) = (args.first(), args.get(1)) {
                 let mut vec: Vec<Value> = Vec::new();
                 for i in (*start as i32)..(*end as i32) {
                     vec.push(Value::Number(i as f32));
                 }
-                Value::Array(vec)
+                Ok(Value::Array(Rc::new(vec)))
             } else {
-                panic!("range expects a number argument");
+                Err(Error::type_error("range expects a number argument"))
+            }
+        })));
+        map
+    }
+    fn object_functions() -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        map.insert("assoc".to_string(), Value::native("assoc", NativeFunction::Pure(|args| {
+            match (args.first(), args.get(1), args.get(2)) {
+                (Some(target), Some(key), Some(value)) => assoc_key(target.clone(), key, value.clone()),
+                _ => Err(Error::type_error("assoc expects a value, a key and a new value")),
+            }
+        })));
+        map.insert("update".to_string(), Value::native("update", NativeFunction::WithInterpreter(|interpreter, args, env| {
+            match (args.first(), args.get(1), args.get(2)) {
+                (Some(target), Some(key), Some(function)) => {
+                    let old = get_key(target, key).unwrap_or(Value::Nil);
+                    let mut call_args = vec![old];
+                    call_args.extend(args[3..].iter().cloned());
+                    let new = interpreter.call_value(function.clone(), call_args, env)?;
+                    Ok(assoc_key(target.clone(), key, new)?)
+                },
+                _ => Err(Error::type_error("update expects a value, a key and a function").into()),
+            }
+        })));
+        map.insert("get-in".to_string(), Value::native("get-in", NativeFunction::Pure(|args| {
+            match (args.first(), args.get(1)) {
+                (Some(target), Some(Value::Array(path))) => {
+                    let mut current = target.clone();
+                    for key in path.iter() {
+                        match get_key(&current, key) {
+                            Some(value) => current = value,
+                            None => return Ok(failure(format!("No key {} in {}", key, current.type_name()))),
+                        }
+                    }
+                    Ok(Value::Ok(Box::new(current)))
+                },
+                _ => Err(Error::type_error("get-in expects a value and a key path array")),
+            }
+        })));
+        map.insert("assoc-in".to_string(), Value::native("assoc-in", NativeFunction::Pure(|args| {
+            match (args.first(), args.get(1), args.get(2)) {
+                (Some(target), Some(Value::Array(path)), Some(value)) => {
+                    update_path(target.clone(), path, &mut |_| Ok::<Value, Error>(value.clone()))
+                },
+                _ => Err(Error::type_error("assoc-in expects a value, a key path array and a new value")),
+            }
+        })));
+        map.insert("update-in".to_string(), Value::native("update-in", NativeFunction::WithInterpreter(|interpreter, args, env| {
+            match (args.first(), args.get(1), args.get(2)) {
+                (Some(target), Some(Value::Array(path)), Some(function)) => {
+                    update_path(target.clone(), path, &mut |old| {
+                        let mut call_args = vec![old];
+                        call_args.extend(args[3..].iter().cloned());
+                        interpreter.call_value(function.clone(), call_args, env.clone())
+                    })
+                },
+                _ => Err(Error::type_error("update-in expects a value, a key path array and a function").into()),
+            }
+        })));
+        map
+    }
+    /// Natives to build code from macros, on top of quasiquote.
+    fn code_functions() -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        map.insert("gensym".to_string(), Value::native("gensym", NativeFunction::WithInterpreter(|interpreter, args, _| {
+            let prefix = match args.first() {
+                Some(Value::String(prefix)) => Symbol::from(prefix),
+                Some(Value::Symbol(prefix)) => *prefix,
+                _ => Symbol::from("g"),
+            };
+            Ok(Value::Symbol(interpreter.gensym(prefix)))
+        })));
+        map.insert("symbol".to_string(), Value::native("symbol", NativeFunction::Pure(|args| {
+            match args.first() {
+                Some(Value::String(name)) => Ok(Value::Symbol(Symbol::from(name))),
+                Some(symbol @ Value::Symbol(_)) => Ok(symbol.clone()),
+                _ => Err(Error::type_error("symbol expects a string")),
+            }
+        })));
+        map.insert("list".to_string(), Value::native("list", NativeFunction::Pure(|args| {
+            Ok(Value::List(Rc::new(args)))
+        })));
+        map.insert("read-string".to_string(), Value::native("read-string", NativeFunction::Pure(|args| {
+            // Several forms are read as a single `do` block.
+            let Some(Value::String(source)) = args.first() else {
+                return Err(Error::type_error("read-string expects a string"));
+            };
+            let tokens = Lexer::tokenize(source);
+            if let Err(error) = Parser::check(&tokens) {
+                return Ok(failure(error.message));
+            }
+            let mut forms = Parser::parse(&mut tokens.into_iter().peekable());
+            match forms.len() {
+                0 => Ok(failure("No form to read".to_string())),
+                1 => Ok(Value::Ok(Box::new(Value::from(&forms.remove(0))))),
+                _ => {
+                    forms.insert(0, Expr::Symbol(symbol::DO));
+                    Ok(Value::Ok(Box::new(Value::from(&Expr::List(forms.into(), Span::default())))))
+                },
+            }
+        })));
+        map.insert("eval".to_string(), Value::native("eval", NativeFunction::WithInterpreter(|interpreter, args, env| {
+            // `(eval form)` runs in the calling scope, `(eval form env)` in a scope from `current-env`.
+            let mut args = args.into_iter();
+            let form = args.next().ok_or_else(|| Error::type_error("eval misses parameters !"))?;
+            let env = match args.next() {
+                Some(Value::Env(given)) => given,
+                None => env,
+                Some(other) => return Err(Error::type_error(format!("eval expects an environment, got {}", other.type_name())).into()),
+            };
+            let expr = form.to_expr(Span::default())?;
+            match interpreter.compute(&mut std::iter::once(&expr).peekable(), env) {
+                // Code built at runtime has no position, the error points at the eval call instead.
+                Err(Signal::Error(mut error)) if error.location == Some(Span::default()) => {
+                    error.location = None;
+                    Err(Signal::Error(error))
+                },
+                result => result,
+            }
+        })));
+        map.insert("current-env".to_string(), Value::native("current-env", NativeFunction::WithEnv(|_, env| {
+            Ok(Value::Env(env))
+        })));
+        map.insert("macroexpand-1".to_string(), Value::native("macroexpand-1", NativeFunction::WithInterpreter(|interpreter, args, env| {
+            let form = args.into_iter().next().ok_or_else(|| Error::type_error("macroexpand-1 misses parameters !"))?;
+            let expanded = interpreter.macroexpand_1(&form.to_expr(Span::default())?, env)?;
+            Ok(expanded.map(|expr| Value::from(&expr)).unwrap_or(form))
+        })));
+        map.insert("macroexpand".to_string(), Value::native("macroexpand", NativeFunction::WithInterpreter(|interpreter, args, env| {
+            let form = args.into_iter().next().ok_or_else(|| Error::type_error("macroexpand misses parameters !"))?;
+            let expanded = interpreter.macroexpand(&form.to_expr(Span::default())?, env)?;
+            Ok(Value::from(&expanded))
+        })));
+        map
+    }
+    fn reference_functions() -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        map.insert("ref".to_string(), Value::native("ref", NativeFunction::Pure(|args| {
+            Ok(Value::Ref(Rc::new(RefCell::new(args.first().cloned().unwrap_or(Value::Nil)))))
+        })));
+        map.insert("deref".to_string(), Value::native("deref", NativeFunction::Pure(|args| {
+            match args.first() {
+                Some(Value::Ref(cell)) => Ok(cell.borrow().clone()),
+                _ => Err(Error::type_error("deref expects a reference")),
+            }
+        })));
+        map.insert("reset!".to_string(), Value::native("reset!", NativeFunction::Pure(|args| {
+            match (args.first(), args.get(1)) {
+                (Some(Value::Ref(cell)), Some(value)) => {
+                    *cell.borrow_mut() = value.clone();
+                    Ok(value.clone())
+                },
+                _ => Err(Error::type_error("reset! expects a reference and a value")),
+            }
+        })));
+        map.insert("swap!".to_string(), Value::native("swap!", NativeFunction::WithInterpreter(|interpreter, args, env| {
+            match (args.first(), args.get(1)) {
+                (Some(Value::Ref(cell)), Some(function)) => {
+                    // The cell is not borrowed during the call so the function may read it.
+                    let old = cell.borrow().clone();
+                    let mut call_args = vec![old];
+                    call_args.extend(args[2..].iter().cloned());
+                    let new = interpreter.call_value(function.clone(), call_args, env)?;
+                    *cell.borrow_mut() = new.clone();
+                    Ok(new)
+                },
+                _ => Err(Error::type_error("swap! expects a reference and a function").into()),
+            }
+        })));
+        map
+    }
+    fn functional_functions() -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        map.insert("apply".to_string(), Value::native("apply", NativeFunction::WithInterpreter(|interpreter, args, env| {
+            // `(apply f a b [c d])` calls `(f a b c d)`.
+            let mut args = args.into_iter();
+            let function = callable("apply", args.next())?;
+            let mut call_args: Vec<Value> = args.collect();
+            match call_args.pop() {
+                Some(Value::Array(spread)) => call_args.extend(spread.iter().cloned()),
+                _ => return Err(Error::type_error("apply expects an array as last argument").into()),
+            }
+            interpreter.call_value(function, call_args, env)
+        })));
+        map.insert("partial".to_string(), Value::native("partial", NativeFunction::Pure(|args| {
+            let mut args = args.into_iter();
+            let function = callable("partial", args.next())?;
+            let bound: Vec<Value> = args.collect();
+            let name = format!("partial {}", function_name(&function));
+            Ok(Value::native(&name, NativeFunction::Closure(Rc::new(move |interpreter, args, env| {
+                let mut call_args = bound.clone();
+                call_args.extend(args);
+                interpreter.call_value(function.clone(), call_args, env)
+            }))))
+        })));
+        map.insert("compose".to_string(), Value::native("compose", NativeFunction::Pure(|args| {
+            // Right to left, `((compose f g) x)` is `(f (g x))`.
+            chain("compose", args.into_iter().rev().collect())
+        })));
+        map.insert("pipe".to_string(), Value::native("pipe", NativeFunction::Pure(|args| {
+            // Left to right, `((pipe f g) x)` is `(g (f x))`.
+            chain("pipe", args)
+        })));
+        map.insert("identity".to_string(), Value::native("identity", NativeFunction::Pure(|args| {
+            args.into_iter().next().ok_or_else(|| Error::type_error("identity misses parameters !"))
+        })));
+        map.insert("constantly".to_string(), Value::native("constantly", NativeFunction::Pure(|args| {
+            let value = args.into_iter().next().ok_or_else(|| Error::type_error("constantly misses parameters !"))?;
+            Ok(Value::native("constantly", NativeFunction::Closure(Rc::new(move |_, _, _| Ok(value.clone())))))
+        })));
+        map.insert("name".to_string(), Value::native("name", NativeFunction::Pure(|args| {
+            let function = callable("name", args.into_iter().next())?;
+            Ok(Value::String(function_name(&function)))
+        })));
+        map.insert("arity".to_string(), Value::native("arity", NativeFunction::Pure(|args| {
+            // `[min max]`, max is nil when the function takes any number of arguments.
+            let (min, max) = match callable("arity", args.into_iter().next())? {
+                Value::Function { params, .. } => {
+                    let max = if params.rest.is_some() { None } else { Some(params.positional.len()) };
+                    (params.required(), max)
+                },
+                Value::ProtocolMethod { protocol, method } => {
+                    let count = protocol.signature(&method).map(|params| params.len()).unwrap_or(0);
+                    (count, Some(count))
+                },
+                // Natives check their own arguments, they accept anything as far as callers know.
+                _ => (0, None),
+            };
+            let max = max.map(|max| Value::Number(max as f32)).unwrap_or(Value::Nil);
+            Ok(Value::Array(Rc::new(vec![Value::Number(min as f32), max])))
+        })));
+        map.insert("params".to_string(), Value::native("params", NativeFunction::Pure(|args| {
+            let names: Vec<String> = match callable("params", args.into_iter().next())? {
+                Value::Function { params, .. } => params.positional.iter().map(|param| param.name.to_string())
+                    .chain(params.rest.iter().map(|rest| format!("& {}", rest)))
+                    .collect(),
+                Value::ProtocolMethod { protocol, method } => protocol.signature(&method).cloned().unwrap_or_default(),
+                _ => vec!["& args".to_string()],
+            };
+            Ok(Value::Array(Rc::new(names.into_iter().map(Value::String).collect())))
+        })));
+        map.insert("annotations".to_string(), Value::native("annotations", NativeFunction::Pure(|args| {
+            let annotations = match callable("annotations", args.into_iter().next())? {
+                Value::Function { annotations, .. } => annotations.iter()
+                    .map(|annotation| Value::String(annotation.to_string()))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            Ok(Value::Array(Rc::new(annotations)))
+        })));
+        map
+    }
+    fn result_functions() -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        map.insert("ok".to_string(), Value::native("ok", NativeFunction::Pure(|args| {
+            Ok(Value::Ok(Box::new(args.first().cloned().unwrap_or(Value::Nil))))
+        })));
+        map.insert("err".to_string(), Value::native("err", NativeFunction::Pure(|args| {
+            Ok(Value::Err(Box::new(args.first().cloned().unwrap_or(Value::Nil))))
+        })));
+        map.insert("ok?".to_string(), Value::native("ok?", NativeFunction::Pure(|args| {
+            match args.first() {
+                Some(Value::Ok(_)) => Ok(Value::Bool(true)),
+                Some(Value::Err(_)) => Ok(Value::Bool(false)),
+                _ => Err(Error::type_error("ok? expects a result")),
+            }
+        })));
+        map.insert("err?".to_string(), Value::native("err?", NativeFunction::Pure(|args| {
+            match args.first() {
+                Some(Value::Ok(_)) => Ok(Value::Bool(false)),
+                Some(Value::Err(_)) => Ok(Value::Bool(true)),
+                _ => Err(Error::type_error("err? expects a result")),
+            }
+        })));
+        map.insert("unwrap".to_string(), Value::native("unwrap", NativeFunction::Pure(|args| {
+            match args.first() {
+                Some(Value::Ok(value)) => Ok(*value.clone()),
+                Some(Value::Err(reason)) => Err(Error::runtime(format!("unwrap called on err({})", reason))),
+                _ => Err(Error::type_error("unwrap expects a result")),
+            }
+        })));
+        map.insert("unwrap-or".to_string(), Value::native("unwrap-or", NativeFunction::Pure(|args| {
+            match (args.first(), args.get(1)) {
+                (Some(Value::Ok(value)), Some(_)) => Ok(*value.clone()),
+                (Some(Value::Err(_)), Some(default)) => Ok(default.clone()),
+                _ => Err(Error::type_error("unwrap-or expects a result and a default value")),
+            }
+        })));
+        map.insert("map-err".to_string(), Value::native("map-err", NativeFunction::WithInterpreter(|interpreter, args, env| {
+            match (args.first(), args.get(1)) {
+                (Some(Value::Ok(value)), Some(_)) => Ok(Value::Ok(value.clone())),
+                (Some(Value::Err(reason)), Some(function)) => {
+                    let mapped = interpreter.call_value(function.clone(), vec![*reason.clone()], env)?;
+                    Ok(Value::Err(Box::new(mapped)))
+                },
+                _ => Err(Error::type_error("map-err expects a result and a function").into()),
             }
         })));
         map
     }
     fn string_functions() -> HashMap<String, Value> {
         let mut map = HashMap::new();
-        map.insert("parse".to_string(), Value::NativeFunction(NativeFunction::Pure(|args| {
-            return match args.get(0) {
+        map.insert("parse".to_string(), Value::native("parse", NativeFunction::Pure(|args| {
+            match args.first() {
                 Some(Value::String(s)) => {
-                    match s.parse::<f32>() {
-                        Ok(f) => Value::Number(f),
-                        Err(_) => Value::Nil,
+                    match s.trim().parse::<f32>() {
+                        Ok(f) => Ok(Value::Ok(Box::new(Value::Number(f)))),
+                        Err(_) => Ok(failure(format!("Cannot parse \"{}\" as a number", s))),
                     }
                 },
-                _ => Value::Nil,
+                _ => Err(Error::type_error("parse expects a string")),
             }
         })));
         map
     }
     fn random_functions() -> HashMap<String, Value> {
         let mut map = HashMap::new();
-        map.insert("rand".to_string(), Value::NativeFunction(NativeFunction::Pure(|_args| {
+        map.insert("rand".to_string(), Value::native("rand", NativeFunction::Pure(|_args| {
             let mut rng = rand::rng();
-            Value::Number(rng.random())
+            Ok(Value::Number(rng.random()))
+        })));
+        map
+    }
+    fn memory_functions() -> HashMap<String, Value> {
+        let mut map = HashMap::new();
+        // Runs the cycle collector now, returns how many environments it freed.
+        map.insert("gc".to_string(), Value::native("gc", NativeFunction::Pure(|_args| {
+            Ok(Value::Number(heap::collect() as f32))
+        })));
+        map.insert("memory".to_string(), Value::native("memory", NativeFunction::Pure(|_args| {
+            Ok(heap::stats().to_value())
         })));
         map
     }
-}
\ No newline at end of file
+}
+
+/// Builds the `err` value returned by fallible natives.
+fn failure(message: String) -> Value {
+    Value::Err(Box::new(Value::String(message)))
+}
+
+/// Checks that every argument of `symbol` is a number.
+fn numbers(symbol: &str, args: &[Value]) -> Result<Vec<f32>, Error> {
+    args.iter().map(|arg| match arg {
+        Value::Number(n) => Ok(*n),
+        other => Err(Error::type_error(format!("< {} > expects numbers, got {}", symbol, other.type_name()))),
+    }).collect()
+}
+
+/// Folds every argument into `identity`, `(+)` and `(*)` return the identity itself.
+fn fold_numbers(symbol: &str, args: &[Value], identity: f32, op: fn(f32, f32) -> f32) -> NativeResult {
+    Ok(Value::Number(numbers(symbol, args)?.into_iter().fold(identity, op)))
+}
+
+/// Folds the arguments from the first one, a single argument is applied to `base`
+/// so that `(- x)` negates and `(/ x)` inverts.
+fn fold_inverse(symbol: &str, args: &[Value], base: f32, op: fn(f32, f32) -> f32) -> NativeResult {
+    let numbers = numbers(symbol, args)?;
+    match numbers.split_first() {
+        Some((first, [])) => Ok(Value::Number(op(base, *first))),
+        Some((first, rest)) => Ok(Value::Number(rest.iter().copied().fold(*first, op))),
+        None => Err(Error::type_error(format!("< {} > expects at least one number", symbol))),
+    }
+}
+
+/// Smaller of two numbers, `nan` when either is. `f32::min` ignores `nan` instead.
+fn minimum(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() { f32::NAN } else { a.min(b) }
+}
+
+/// Larger of two numbers, `nan` when either is.
+fn maximum(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() { f32::NAN } else { a.max(b) }
+}
+
+/// Checks that `symbol` received exactly one number.
+fn unary_number(symbol: &str, args: &[Value]) -> Result<f32, Error> {
+    match numbers(symbol, args)?[..] {
+        [x] => Ok(x),
+        _ => Err(Error::type_error(format!("{} expects one number", symbol))),
+    }
+}
+
+/// Checks that `symbol` received exactly two numbers.
+fn binary_numbers(symbol: &str, args: &[Value]) -> Result<[f32; 2], Error> {
+    match numbers(symbol, args)?[..] {
+        [a, b] => Ok([a, b]),
+        _ => Err(Error::type_error(format!("{} expects two numbers", symbol))),
+    }
+}
+
+/// Integer value of a number for bitwise operations.
+fn integer(symbol: &str, n: f32) -> Result<i64, Error> {
+    if n.is_finite() && n.fract() == 0.0 {
+        Ok(n as i64)
+    } else {
+        Err(Error::type_error(format!("{} expects integers, got {}", symbol, n)))
+    }
+}
+
+fn binary_integers(symbol: &str, args: &[Value]) -> Result<[i64; 2], Error> {
+    let [a, b] = binary_numbers(symbol, args)?;
+    Ok([integer(symbol, a)?, integer(symbol, b)?])
+}
+
+/// Chained comparison, `(< a b c)` holds when every neighbour pair does.
+fn compare_numbers(symbol: &str, args: &[Value], op: fn(f32, f32) -> bool) -> NativeResult {
+    let numbers = numbers(symbol, args)?;
+    if numbers.is_empty() {
+        return Err(Error::type_error(format!("< {} > expects at least one number", symbol)));
+    }
+    Ok(Value::Bool(numbers.windows(2).all(|pair| op(pair[0], pair[1]))))
+}
+
+/// Whether two booleans, numbers or strings are equal, for `==` and `!=`.
+fn equal(symbol: &str, a: &Value, b: &Value) -> Result<bool, Error> {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+        (Value::Number(a), Value::Number(b)) => Ok(a == b),
+        (Value::String(a), Value::String(b)) => Ok(a == b),
+        _ => Err(Error::type_error(format!("< {} > compares two booleans, numbers or strings, got {} and {}", symbol, a.type_name(), b.type_name()))),
+    }
+}
+
+/// Checks that `symbol` received a function as first argument.
+fn callable(symbol: &str, value: Option<Value>) -> Result<Value, Error> {
+    match value {
+        Some(function @ (Value::Function { .. } | Value::NativeFunction(_) | Value::ProtocolMethod { .. })) => Ok(function),
+        Some(other) => Err(Error::type_error(format!("{} expects a function, got {}", symbol, other.type_name()))),
+        None => Err(Error::type_error(format!("{} misses parameters !", symbol))),
+    }
+}
+
+fn function_name(function: &Value) -> String {
+    match function {
+        Value::Function { name, .. } => name.to_string(),
+        Value::NativeFunction(native) => native.name.clone(),
+        Value::ProtocolMethod { method, .. } => method.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Function calling `functions` one after the other, each on the result of the previous one.
+fn chain(symbol: &str, functions: Vec<Value>) -> NativeResult {
+    let functions = functions.into_iter().map(|function| callable(symbol, Some(function))).collect::<Result<Vec<Value>, Error>>()?;
+    Ok(Value::native(symbol, NativeFunction::Closure(Rc::new(move |interpreter, args, env| {
+        let mut args = args;
+        for function in &functions {
+            args = vec![interpreter.call_value(function.clone(), args, env.clone())?];
+        }
+        Ok(args.into_iter().next().unwrap_or(Value::Nil))
+    }))))
+}
+
+/// Reads a line from stdin as `ok(line)`, or `err` once the input is exhausted.
+fn read_line() -> Value {
+    let mut line = String::new();
+    match std::io::stdin().read_line(&mut line) {
+        Ok(0) => failure("End of input".to_string()),
+        Ok(_) => Value::Ok(Box::new(Value::String(line.trim_end().to_string()))),
+        Err(e) => failure(e.to_string()),
+    }
+}
+
+/// Reads `key` from an object (field name) or an array (index).
+fn get_key(target: &Value, key: &Value) -> Option<Value> {
+    match (target, key) {
+        (Value::Object { class: _, attrs }, Value::String(field)) => attrs.get(field).cloned(),
+        (Value::Array(arr), Value::Number(index)) if *index >= 0.0 => arr.get(*index as usize).cloned(),
+        _ => None,
+    }
+}
+
+/// Returns a copy of `target` where `key` is bound to `value`. The original is left untouched.
+fn assoc_key(target: Value, key: &Value, value: Value) -> Result<Value, Error> {
+    match (target, key) {
+        (Value::Object { class, mut attrs }, Value::String(field)) => {
+            if !attrs.contains_key(field) {
+                return Err(Error::undefined(format!("Unknown field {} for {}", field, class)));
+            }
+            Rc::make_mut(&mut attrs).insert(field.clone(), value);
+            Ok(Value::Object { class, attrs })
+        },
+        (Value::Array(mut arr), Value::Number(index)) => {
+            let i = *index as usize;
+            if *index < 0.0 || i > arr.len() {
+                return Err(Error::runtime(format!("Index {} out of bounds for array of length {}", index, arr.len())));
+            }
+            let items = Rc::make_mut(&mut arr);
+            if i == items.len() {
+                items.push(value);
+            } else {
+                items[i] = value;
+            }
+            Ok(Value::Array(arr))
+        },
+        (target, key) => Err(Error::type_error(format!("Cannot associate key {} in {}", key, target.type_name()))),
+    }
+}
+
+/// Rebuilds `target` along `path`, replacing the value found at the end of the path by `f(old)`.
+fn update_path<E: From<Error>>(target: Value, path: &[Value], f: &mut dyn FnMut(Value) -> Result<Value, E>) -> Result<Value, E> {
+    match path.split_first() {
+        None => f(target),
+        Some((key, rest)) => {
+            let child = get_key(&target, key).unwrap_or(Value::Nil);
+            let new_child = update_path(child, rest, f)?;
+            Ok(assoc_key(target, key, new_child)?)
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+
+/// Interned identifier. Symbols with the same name share one id, so comparing
+/// or hashing them costs the same as for an integer.
+///
+/// The table lives as long as the program: names are never freed. Like the
+/// values built from them, symbols belong to the thread that interned them.
+///
+/// Gensyms are not interned, so macros can mint them without growing the
+/// table: one pairs an interned prefix with a generation number and prints as
+/// `prefix__n`. The trade-off is that reading that name back, with `symbol` or
+/// `read-string`, interns a plain symbol that is not equal to the gensym, and
+/// `as_str` only gives the prefix.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u64);
+
+struct Interner {
+    ids: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+/// Declares the symbols known to the interpreter, with ids following their order.
+macro_rules! known_symbols {
+    ($($constant:ident = $name:literal,)*) => {
+        const KNOWN: &[&str] = &[$($name,)*];
+        known_symbols!(@ids 0; $($constant,)*);
+    };
+    (@ids $id:expr; $constant:ident, $($rest:ident,)*) => {
+        pub const $constant: Symbol = Symbol($id);
+        known_symbols!(@ids $id + 1; $($rest,)*);
+    };
+    (@ids $id:expr;) => {};
+}
+
+known_symbols! {
+    DO = "do",
+    IF = "if",
+    COND = "cond",
+    WHEN = "when",
+    UNLESS = "unless",
+    CASE = "case",
+    AND = "and",
+    OR = "or",
+    VAR = "var",
+    STRUCT = "struct",
+    PROTOCOL = "protocol",
+    IMPL = "impl",
+    DOT = ".",
+    BREAK = "break",
+    CONTINUE = "continue",
+    RETURN = "return",
+    QUESTION = "?",
+    THROW = "throw",
+    TRY = "try",
+    FOR = "for",
+    WHILE = "while",
+    THREAD_FIRST = "->",
+    THREAD_LAST = "->>",
+    THREAD_AS = "as->",
+    LET = "let",
+    FN = "fn",
+    DEFMACRO = "defmacro",
+    QUOTE = "quote",
+    QUASIQUOTE = "quasiquote",
+    UNQUOTE = "unquote",
+    UNQUOTE_SPLICING = "unquote-splicing",
+    SET = "set",
+    MATCH = "match",
+    ELSE = "else",
+    CATCH = "catch",
+    FINALLY = "finally",
+    AMPERSAND = "&",
+    NIL = "nil",
+    TRUE = "true",
+    FALSE = "false",
+}
+
+impl Interner {
+    fn new() -> Self {
+        let mut interner = Interner { ids: HashMap::new(), names: Vec::new() };
+        for name in KNOWN {
+            interner.intern(name);
+        }
+        interner
+    }
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.ids.get(name) {
+            return symbol;
+        }
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        let symbol = Symbol(self.names.len() as u64);
+        self.ids.insert(name, symbol);
+        self.names.push(name);
+        symbol
+    }
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(name))
+    }
+
+    /// A symbol unequal to every other, printed as `prefix__generation`.
+    /// Generations start at 1; the caller keeps them unique.
+    pub fn gensym(prefix: Symbol, generation: u32) -> Symbol {
+        Symbol((generation as u64) << 32 | prefix.id() as u64)
+    }
+
+    pub fn is_gensym(self) -> bool {
+        self.generation() != 0
+    }
+
+    /// Index of the interned name, the low half.
+    fn id(self) -> u32 {
+        self.0 as u32
+    }
+
+    /// Zero for interned symbols, the high half.
+    fn generation(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    /// The interned name, or the prefix for a gensym.
+    pub fn as_str(self) -> &'static str {
+        INTERNER.with(|interner| interner.borrow().names[self.id() as usize])
+    }
+
+    /// How many names have been interned on this thread.
+    pub fn interned_count() -> usize {
+        INTERNER.with(|interner| interner.borrow().names.len())
+    }
+
+    /// `:name` symbols, used for keyword arguments.
+    pub fn is_keyword(self) -> bool {
+        let name = self.as_str();
+        name.len() > 1 && name.starts_with(':')
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::intern(&name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        !self.is_gensym() && self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        !self.is_gensym() && self.as_str() == *other
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_gensym() {
+            write!(f, "{}__{}", self.as_str(), self.generation())
+        } else {
+            f.write_str(self.as_str())
+        }
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.to_string())
+    }
+}
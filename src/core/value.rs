@@ -1,10 +1,16 @@
-use std::cell::RefCell;
+use std::cell::{OnceCell, RefCell};
 use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 use crate::core::annotation::Annotation;
+use crate::core::compiler::Chunk;
 use crate::core::env::Env;
-use crate::core::expr::Expr;
-use crate::core::stdlib::NativeFunction;
+use crate::core::error::Error;
+use crate::core::expr::{Expr, Span};
+use crate::core::params::Params;
+use crate::core::protocol::Protocol;
+use crate::core::stdlib::{Native, NativeFunction};
+use crate::core::symbol::{self, Symbol};
 
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -12,48 +18,114 @@ pub enum Value {
     String(String),
     Number(f32),
     Bool(bool),
-    Array(Vec<Value>),
+    Array(Rc<Vec<Value>>),
+    Symbol(Symbol),
+    List(Rc<Vec<Value>>),
     Function {
-        params: Vec<String>,
-        body: Box<Expr>,
+        name: Rc<str>,
+        params: Rc<Params>,
+        body: Rc<Expr>,
         func_env: Rc<RefCell<Env>>,
-        annotations: Vec<Annotation>,
+        annotations: Rc<[Annotation]>,
+        /// Body compiled by the VM on the first call, shared by copies of the function.
+        chunk: Rc<OnceCell<Rc<Chunk>>>,
+    },
+    Object{class: String, attrs: Rc<HashMap<String, Value>>},
+    Ref(Rc<RefCell<Value>>),
+    Env(Rc<RefCell<Env>>),
+    Ok(Box<Value>),
+    Err(Box<Value>),
+    NativeFunction(Rc<Native>),
+    ProtocolMethod {
+        protocol: Rc<Protocol>,
+        method: String,
     },
-    Object{class: String, attrs: HashMap<String, Value>},
-    NativeFunction(NativeFunction)
 }
 
-impl Value {
-    pub fn to_string(&self) -> String {
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             //Value::Nil => "nil".to_string(),
-            Value::String(s) => s.clone(),
-            Value::Number(n) => n.to_string(),
-            Value::Bool(b) => b.to_string(),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
             Value::Array(arr) => {
-                let mut res = String::from("[");
-                for v in arr {
-                    res.push_str(&v.to_string());
-                    res.push(' ');
-                }
-                res.pop();
-                res.push_str("]");
-                res
+                let items: Vec<String> = arr.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", items.join(" "))
             },
             Value::Object{class, attrs} => {
                 let mut res = String::from("");
-                res.push_str(&class);
+                res.push_str(class);
                 res.push_str(" -> ");
-                for (k, v) in attrs {
-                    res.push_str(&k);
+                for (k, v) in attrs.iter() {
+                    res.push_str(k);
                     res.push('=');
                     res.push_str(&v.to_string());
                     res.push_str(" | ");
                 }
-                res
+                write!(f, "{}", res)
+            },
+            Value::Symbol(name) => write!(f, "{}", name),
+            Value::List(items) => {
+                // Strings keep their quotes so that printed code reads back the same.
+                let items: Vec<String> = items.iter().map(|item| match item {
+                    Value::String(s) => format!("\"{}\"", s),
+                    other => other.to_string(),
+                }).collect();
+                write!(f, "({})", items.join(" "))
             },
-            Value::Nil => String::from("nil"),
-            _ => "".to_string()
+            Value::Ref(cell) => write!(f, "ref({})", cell.borrow()),
+            Value::Env(_) => write!(f, "<env>"),
+            Value::Ok(value) => write!(f, "ok({})", value),
+            Value::Err(value) => write!(f, "err({})", value),
+            Value::Nil => write!(f, "nil"),
+            Value::Function { name, .. } => write!(f, "<fn {}>", name),
+            Value::NativeFunction(native) => write!(f, "<native {}>", native.name),
+            _ => Ok(())
+        }
+    }
+}
+
+impl Value {
+    pub fn native(name: &str, function: NativeFunction) -> Value {
+        Value::NativeFunction(Rc::new(Native { name: name.to_string(), function }))
+    }
+
+    /// `nil` and `false` are falsy, every other value is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    /// Runtime type name, used by `typeof` and for protocol dispatch.
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::Nil => "Nil".to_string(),
+            Value::String(_) => "String".to_string(),
+            Value::Number(_) => "Number".to_string(),
+            Value::Bool(_) => "Bool".to_string(),
+            Value::Array(_) => "Array".to_string(),
+            Value::Symbol(_) => "Symbol".to_string(),
+            Value::List(_) => "List".to_string(),
+            Value::Function { .. } | Value::NativeFunction(_) | Value::ProtocolMethod { .. } => "Function".to_string(),
+            Value::Object { class, attrs: _ } => class.clone(),
+            Value::Ref(_) => "Ref".to_string(),
+            Value::Env(_) => "Env".to_string(),
+            Value::Ok(_) | Value::Err(_) => "Result".to_string(),
+        }
+    }
+
+    /// Turns code built by a macro or `quote` back into a form, lists get `span`
+    /// since they have no position of their own.
+    pub fn to_expr(&self, span: Span) -> Result<Expr, Error> {
+        match self {
+            Value::Number(n) => Ok(Expr::Number(*n)),
+            Value::String(s) => Ok(Expr::String(s.clone())),
+            Value::Symbol(name) => Ok(Expr::Symbol(*name)),
+            Value::Nil => Ok(Expr::Symbol(symbol::NIL)),
+            Value::Bool(b) => Ok(Expr::Symbol(if *b { symbol::TRUE } else { symbol::FALSE })),
+            Value::List(items) => Ok(Expr::List(items.iter().map(|item| item.to_expr(span)).collect::<Result<_, _>>()?, span)),
+            Value::Array(items) => Ok(Expr::Array(items.iter().map(|item| item.to_expr(span)).collect::<Result<_, _>>()?)),
+            other => Err(Error::type_error(format!("A {} value cannot be turned into code", other.type_name()))),
         }
     }
 
@@ -63,4 +135,17 @@ impl Value {
             _ => panic!("Expected a number, got {:?}", self),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Quotes a form: lists and symbols become data instead of being evaluated.
+impl From<&Expr> for Value {
+    fn from(expr: &Expr) -> Self {
+        match expr {
+            Expr::Number(n) => Value::Number(*n),
+            Expr::String(s) => Value::String(s.clone()),
+            Expr::Symbol(name) => Value::Symbol(*name),
+            Expr::List(items, _) => Value::List(Rc::new(items.iter().map(Value::from).collect())),
+            Expr::Array(items) => Value::Array(Rc::new(items.iter().map(Value::from).collect())),
+        }
+    }
+}
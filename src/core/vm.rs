@@ -0,0 +1,382 @@
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use crate::core::compiler::{Chunk, Compiler, Op};
+use crate::core::env::Env;
+use crate::core::error::Error;
+use crate::core::expr::Expr;
+use crate::core::interpreter::Interpreter;
+use crate::core::params::Params;
+use crate::core::resolver::Binding;
+use crate::core::signal::{EvalResult, Signal};
+use crate::core::symbol::Symbol;
+use crate::core::value::Value;
+
+/// How programs and function bodies are run: by walking the `Expr` tree or as
+/// bytecode on the stack machine. Both share environments and natives.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Engine {
+    #[default]
+    Tree,
+    Vm,
+}
+
+/// A running loop, where `break` and `continue` resume.
+#[derive(Clone, Default)]
+struct LoopFrame {
+    exit: usize,
+    next: usize,
+    keep_last: bool,
+    stack: usize,
+    scopes: usize,
+    iterators: usize,
+    body_env: Option<Rc<RefCell<Env>>>,
+}
+
+/// Iteration state of a running `for`.
+struct ForFrame {
+    names: usize,
+    ranges: Vec<Vec<f32>>,
+    index: usize,
+}
+
+/// State of one chunk being run.
+struct Frame {
+    ip: usize,
+    stack: Vec<Value>,
+    scopes: Vec<Rc<RefCell<Env>>>,
+    loops: Vec<LoopFrame>,
+    iterators: Vec<ForFrame>,
+}
+
+impl Frame {
+    fn scope(&self) -> Rc<RefCell<Env>> {
+        self.scopes.last().cloned().unwrap_or_default()
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap_or(Value::Nil)
+    }
+
+    fn pop_many(&mut self, count: usize) -> Vec<Value> {
+        self.stack.split_off(self.stack.len().saturating_sub(count))
+    }
+
+    /// Drops the scopes and iterators opened inside the innermost loop, whose frame is returned.
+    fn unwind(&mut self) -> LoopFrame {
+        let current = self.loops.last().cloned().unwrap_or_default();
+        self.scopes.truncate(current.scopes);
+        self.iterators.truncate(current.iterators);
+        current
+    }
+}
+
+/// Number of compiled bodies cached before the first eviction.
+const MIN_CACHED: usize = 64;
+
+/// Code the compiled body of an entry was made from. Until the entry is
+/// evicted, the weak reference keeps the address from being reused by another body.
+enum Source {
+    Items(Weak<[Expr]>),
+    Body(Weak<Expr>),
+}
+
+impl Source {
+    fn is_alive(&self) -> bool {
+        match self {
+            Source::Items(items) => items.strong_count() > 0,
+            Source::Body(body) => body.strong_count() > 0,
+        }
+    }
+}
+
+/// Compiled function bodies with the parameters they were resolved against.
+/// Function values own their chunk, the cache only lets the functions made by
+/// the same `fn` form share it: once they are all gone, as happens to the ones
+/// defined by `eval` or by macros, the entry is evicted.
+pub(crate) struct ChunkCache {
+    entries: HashMap<*const Expr, (Source, Params, Weak<Chunk>)>,
+    limit: usize,
+}
+
+impl Default for ChunkCache {
+    fn default() -> Self {
+        ChunkCache { entries: HashMap::new(), limit: MIN_CACHED }
+    }
+}
+
+impl ChunkCache {
+    /// Chunk of a function body, compiled when no living function shares it.
+    fn get(&mut self, params: &Params, body: &Rc<Expr>) -> Rc<Chunk> {
+        // Functions made by the same `fn` form share the items of their body.
+        let (key, source) = match body.as_ref() {
+            Expr::List(items, _) | Expr::Array(items) => (items.as_ptr(), Source::Items(Rc::downgrade(items))),
+            other => (other as *const Expr, Source::Body(Rc::downgrade(body))),
+        };
+        if let Some((cached, compiled, chunk)) = self.entries.get(&key)
+            && cached.is_alive()
+            && compiled.binds_like(params)
+            && let Some(chunk) = chunk.upgrade() {
+            return chunk;
+        }
+        if self.entries.len() >= self.limit {
+            self.entries.retain(|_, (source, _, chunk)| source.is_alive() && chunk.strong_count() > 0);
+            self.limit = MIN_CACHED.max(self.entries.len() * 2);
+        }
+        let chunk = Rc::new(Compiler::function(params, body));
+        self.entries.insert(key, (source, params.clone(), Rc::downgrade(&chunk)));
+        chunk
+    }
+}
+
+/// Scope `depth` levels above `scope`, unless a scope on the way has names
+/// the resolver did not see, which could shadow the addressed local.
+fn addressed(scope: &Rc<RefCell<Env>>, depth: usize) -> Option<Rc<RefCell<Env>>> {
+    let mut env = scope.clone();
+    for _ in 0..depth {
+        let parent = {
+            let current = env.borrow();
+            if current.variables.is_open() {
+                return None;
+            }
+            current.parent.clone()?
+        };
+        env = parent;
+    }
+    Some(env)
+}
+
+/// Reads a variable through its binding, by name from `scope` when the binding cannot be trusted.
+fn lookup(scope: &Rc<RefCell<Env>>, binding: Binding, name: Symbol) -> Option<Value> {
+    let found = match binding {
+        Binding::Local(address) => addressed(scope, address.depth)
+            .and_then(|env| env.borrow().variables.slot(address.slot, name).cloned()),
+        Binding::Free { outer } => addressed(scope, outer).and_then(|env| env.borrow().get(name)),
+    };
+    found.or_else(|| scope.borrow().get(name))
+}
+
+/// Replaces the value of a variable, which `Op::SetGuard` checked exists. See `lookup`.
+fn assign(scope: &Rc<RefCell<Env>>, binding: Binding, name: Symbol, value: Value) {
+    match binding {
+        Binding::Local(address) => {
+            if let Some(env) = addressed(scope, address.depth)
+                && let Some(slot) = env.borrow_mut().variables.slot_mut(address.slot, name) {
+                *slot = value;
+                return;
+            }
+            scope.borrow_mut().set(name, value);
+        },
+        // No scope skipped holds the name, it is bound further up.
+        Binding::Free { outer } => match addressed(scope, outer) {
+            Some(env) => env.borrow_mut().set(name, value),
+            None => scope.borrow_mut().set(name, value),
+        },
+    }
+}
+
+impl Interpreter {
+    /// Runs a function body, compiling it on its first call.
+    pub(crate) fn run_compiled(&mut self, params: &Params, body: &Rc<Expr>, chunk: &OnceCell<Rc<Chunk>>, env: Rc<RefCell<Env>>) -> EvalResult {
+        let chunk = chunk.get_or_init(|| self.chunks.get(params, body)).clone();
+        self.run_chunk(&chunk, env)
+    }
+
+    pub(crate) fn run_chunk(&mut self, chunk: &Chunk, env: Rc<RefCell<Env>>) -> EvalResult {
+        let loop_depth = self.loop_depth;
+        let mut frame = Frame { ip: 0, stack: Vec::new(), scopes: vec![env], loops: Vec::new(), iterators: Vec::new() };
+        while frame.ip < chunk.code.len() {
+            let op = chunk.code[frame.ip];
+            frame.ip += 1;
+            match self.step(op, chunk, &mut frame) {
+                Ok(()) => {},
+                Err(Signal::Break(value)) if !frame.loops.is_empty() => {
+                    let exited = frame.unwind();
+                    frame.stack.truncate(exited.stack - usize::from(exited.keep_last));
+                    frame.stack.push(value);
+                    frame.loops.pop();
+                    self.loop_depth -= 1;
+                    frame.ip = exited.exit;
+                },
+                Err(Signal::Continue) if !frame.loops.is_empty() => {
+                    let current = frame.unwind();
+                    frame.stack.truncate(current.stack);
+                    frame.ip = current.next;
+                },
+                Err(signal) => {
+                    self.loop_depth = loop_depth;
+                    return Err(match signal {
+                        // Same rule as `handle_list`: the innermost form gives its location.
+                        Signal::Error(mut error) if error.location.is_none() => {
+                            error.location = chunk.spans[frame.ip - 1];
+                            Signal::Error(error)
+                        },
+                        signal => signal,
+                    });
+                },
+            }
+        }
+        Ok(frame.pop())
+    }
+
+    fn step(&mut self, op: Op, chunk: &Chunk, frame: &mut Frame) -> Result<(), Signal> {
+        match op {
+            Op::Constant(index) => frame.stack.push(chunk.constants[index].clone()),
+            Op::Get { name, binding } => {
+                let name = chunk.names[name];
+                let value = lookup(&frame.scope(), binding, name)
+                    .ok_or_else(|| Error::undefined(format!("Undefined symbol: {}", name)))?;
+                frame.stack.push(value);
+            },
+            Op::Define(name) => {
+                let name = chunk.names[name];
+                let value = frame.stack.last().cloned().unwrap_or(Value::Nil);
+                let scope = frame.scope();
+                if scope.borrow().variables.contains_key(name) {
+                    return Err(Error::runtime(format!("Variable {} already defined ! Use set to modify its value.", name)).into());
+                }
+                scope.borrow_mut().variables.declare(name, value);
+            },
+            Op::Bind(list) => {
+                let names = &chunk.name_lists[list];
+                let values = frame.pop_many(names.len());
+                let scope = frame.scope();
+                for (name, value) in names.iter().zip(values) {
+                    scope.borrow_mut().variables.declare(*name, value);
+                }
+            },
+            Op::SetGuard { name, binding, form, skip } => {
+                let current = lookup(&frame.scope(), binding, chunk.names[name]);
+                if matches!(current, None | Some(Value::Object { .. })) {
+                    let value = self.compute(&mut std::iter::once(&chunk.forms[form]).peekable(), frame.scope())?;
+                    frame.stack.push(value);
+                    frame.ip = skip;
+                }
+            },
+            Op::Set { name, binding } => {
+                let value = frame.pop();
+                assign(&frame.scope(), binding, chunk.names[name], value);
+                frame.stack.push(Value::Nil);
+            },
+            Op::Pop => {
+                frame.pop();
+            },
+            Op::Nip => {
+                let below = frame.stack.len() - 2;
+                frame.stack.swap_remove(below);
+            },
+            Op::Jump(target) => frame.ip = target,
+            Op::JumpIfFalse(target) => {
+                if !frame.pop().is_truthy() {
+                    frame.ip = target;
+                }
+            },
+            Op::JumpIfTrue(target) => {
+                if frame.pop().is_truthy() {
+                    frame.ip = target;
+                }
+            },
+            Op::JumpIfFalseOrPop(target) | Op::JumpIfTrueOrPop(target) => {
+                let truthy = frame.stack.last().is_some_and(Value::is_truthy);
+                if truthy == matches!(op, Op::JumpIfTrueOrPop(_)) {
+                    frame.ip = target;
+                } else {
+                    frame.pop();
+                }
+            },
+            Op::Array(count) => {
+                let items = frame.pop_many(count);
+                frame.stack.push(Value::Array(Rc::new(items)));
+            },
+            Op::Callee { name, binding, form, skip } => {
+                let scope = frame.scope();
+                let name = chunk.names[name];
+                let is_macro = scope.borrow().macro_exists(name).is_some();
+                let function = if is_macro { None } else { lookup(&scope, binding, name) };
+                match function {
+                    Some(function @ (Value::Function { .. } | Value::NativeFunction(_) | Value::ProtocolMethod { .. })) => {
+                        frame.stack.push(function);
+                    },
+                    _ => {
+                        let value = self.compute(&mut std::iter::once(&chunk.forms[form]).peekable(), scope)?;
+                        frame.stack.push(value);
+                        frame.ip = skip;
+                    },
+                }
+            },
+            Op::Call(count) => {
+                let args = frame.pop_many(count);
+                let function = frame.pop();
+                let value = self.call_value(function, args, frame.scope())?;
+                frame.stack.push(value);
+            },
+            Op::Eval(form) => {
+                let value = self.compute(&mut std::iter::once(&chunk.forms[form]).peekable(), frame.scope())?;
+                frame.stack.push(value);
+            },
+            Op::PushScope => {
+                let scope = Env::child(&frame.scope());
+                frame.scopes.push(scope);
+            },
+            Op::PopScope => {
+                frame.scopes.pop();
+            },
+            Op::LoopStart { exit, next, keep_last } => {
+                let body_env = keep_last.then(|| Env::child(&frame.scope()));
+                frame.loops.push(LoopFrame {
+                    exit,
+                    next,
+                    keep_last,
+                    stack: frame.stack.len(),
+                    scopes: frame.scopes.len(),
+                    iterators: frame.iterators.len(),
+                    body_env,
+                });
+                self.loop_depth += 1;
+            },
+            Op::LoopScope => {
+                if let Some(body_env) = frame.loops.last().and_then(|lp| lp.body_env.clone()) {
+                    frame.scopes.push(body_env);
+                }
+            },
+            Op::LoopEnd => {
+                frame.loops.pop();
+                self.loop_depth -= 1;
+            },
+            Op::ForInit { names, count } => {
+                // Like the tree walker, values that are not arrays of numbers are skipped.
+                let ranges = frame.pop_many(count).into_iter().filter_map(|range| match range {
+                    Value::Array(items) => Some(items.iter().filter_map(|item| match item {
+                        Value::Number(n) => Some(*n),
+                        _ => None,
+                    }).collect()),
+                    _ => None,
+                }).collect();
+                frame.iterators.push(ForFrame { names, ranges, index: 0 });
+            },
+            Op::ForNext(target) => {
+                let scope = frame.scope();
+                let Some(iterator) = frame.iterators.last_mut() else {
+                    frame.ip = target;
+                    return Ok(());
+                };
+                let max = iterator.ranges.iter().map(|range| range.len()).min().unwrap_or(0);
+                if iterator.index >= max {
+                    frame.ip = target;
+                    return Ok(());
+                }
+                for (name, range) in chunk.name_lists[iterator.names].iter().zip(&iterator.ranges) {
+                    scope.borrow_mut().variables.declare(*name, Value::Number(range[iterator.index]));
+                }
+                iterator.index += 1;
+            },
+            Op::ForEnd => {
+                frame.iterators.pop();
+            },
+            Op::Break => return Err(Signal::Break(frame.pop())),
+            Op::Continue => return Err(Signal::Continue),
+            Op::Return => return Err(Signal::Return(frame.pop())),
+        }
+        Ok(())
+    }
+}
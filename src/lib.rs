@@ -7,5 +7,18 @@ pub mod core {
     pub mod env;
     pub mod annotation;
     pub mod pattern;
+    pub mod class;
+    pub mod params;
+    pub mod macros;
+    pub mod protocol;
+    pub mod signal;
+    pub mod error;
     pub mod stdlib;
+    pub mod compiler;
+    pub mod optimizer;
+    pub mod resolver;
+    pub mod symbol;
+    pub mod vm;
+    pub mod heap;
+    pub mod embed;
 }
\ No newline at end of file
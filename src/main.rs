@@ -1,9 +1,17 @@
 use std::fs;
-use std::path::PathBuf;
-use clap::{Parser, Subcommand};
-use vega::core::expr::Expr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+use clap::{Parser, Subcommand, ValueEnum};
+use vega::core::expr::{Expr, Span};
 use vega::core::interpreter::Interpreter;
 use vega::core::lexer::{Lexer, Token};
+use vega::core::optimizer::Optimizer;
+use vega::core::signal::Signal;
+use vega::core::stdlib::NativeFunction;
+use vega::core::symbol::Symbol;
+use vega::core::value::Value;
+use vega::core::vm::Engine;
 
 #[derive(Parser)]
 #[command(name = "vega")]
@@ -17,24 +25,180 @@ struct Cli {
 enum Commands {
     Run {
         file: PathBuf,
+        /// Walks the syntax tree or runs compiled bytecode
+        #[arg(long, value_enum, default_value_t = EngineArg::Tree)]
+        engine: EngineArg,
+        /// Folds constants and drops dead branches before running
+        #[arg(long)]
+        optimize: bool,
     },
+    /// Prints the program with every macro call expanded
+    Expand {
+        file: PathBuf,
+        /// Prints the program as the optimizer leaves it
+        #[arg(long)]
+        optimize: bool,
+    },
+    /// Times every script of a directory under both engines
+    Bench {
+        #[arg(default_value = "benches")]
+        dir: PathBuf,
+        /// Timed runs per script and engine, after a first run showing the output
+        #[arg(long, default_value_t = 10)]
+        samples: usize,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EngineArg {
+    Tree,
+    Vm,
+}
+
+impl From<EngineArg> for Engine {
+    fn from(engine: EngineArg) -> Self {
+        match engine {
+            EngineArg::Tree => Engine::Tree,
+            EngineArg::Vm => Engine::Vm,
+        }
+    }
 }
 
-fn main() {
+/// Exits with a failure status when the program could not be read, parsed or run.
+fn main() -> ExitCode {
     let cli = Cli::parse();
     match cli.command {
-        Commands::Run { file } => {
+        Commands::Run { file, engine, optimize } => {
+            match fs::read_to_string(&file) {
+                Ok(content) => evaluate(content.as_str(), engine.into(), optimize),
+                Err(e) => {
+                    eprintln!("Error reading file {:?}: {}", file, e);
+                    ExitCode::FAILURE
+                },
+            }
+        }
+        Commands::Expand { file, optimize } => {
             match fs::read_to_string(&file) {
-                Ok(content) => evaluate(content.as_str()),
-                Err(e) => eprintln!("Error reading file {:?}: {}", file, e),
+                Ok(content) => expand(content.as_str(), optimize),
+                Err(e) => {
+                    eprintln!("Error reading file {:?}: {}", file, e);
+                    ExitCode::FAILURE
+                },
             }
         }
+        Commands::Bench { dir, samples } => bench(&dir, samples.max(1)),
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<Expr>, Signal> {
+    let tokens: Vec<(Token, Span)> = Lexer::tokenize(input);
+    vega::core::parser::Parser::check(&tokens)?;
+    Ok(vega::core::parser::Parser::parse(&mut tokens.into_iter().peekable()))
+}
+
+fn report(signal: Signal) -> ExitCode {
+    match signal {
+        Signal::Error(error) => eprintln!("{}", error),
+        Signal::Throw(value) => eprintln!("Uncaught exception: {}", value),
+        signal => eprintln!("Uncaught signal: {:?}", signal),
+    }
+    ExitCode::FAILURE
+}
+
+fn evaluate(input: &str, engine: Engine, optimize: bool) -> ExitCode {
+    let mut exprs = match parse(input) {
+        Ok(exprs) => exprs,
+        Err(signal) => return report(signal),
+    };
+    if optimize {
+        exprs = Optimizer::optimize(&exprs);
+    }
+    let mut interpreter = Interpreter::with_engine(engine);
+    match interpreter.execute(&exprs) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(signal) => report(signal),
+    }
+}
+
+/// Scripts that cannot be read or that fail are reported and left out of the table.
+fn bench(dir: &Path, samples: usize) -> ExitCode {
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "vega"))
+            .collect(),
+        Err(e) => {
+            eprintln!("Error reading directory {:?}: {}", dir, e);
+            return ExitCode::FAILURE;
+        },
+    };
+    files.sort();
+    let mut status = ExitCode::SUCCESS;
+    let mut rows = Vec::new();
+    for file in files {
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!("Error reading file {:?}: {}", file, e);
+                status = ExitCode::FAILURE;
+                continue;
+            },
+        };
+        let timings: Result<Vec<String>, Signal> = parse(content.as_str()).and_then(|exprs| {
+            [Engine::Tree, Engine::Vm].into_iter().map(|engine| {
+                time(&exprs, engine, false)?;
+                let runs = (0..samples).map(|_| time(&exprs, engine, true)).collect::<Result<Vec<f64>, Signal>>()?;
+                let mean = runs.iter().sum::<f64>() / runs.len() as f64;
+                let deviation = (runs.iter().map(|run| (run - mean).powi(2)).sum::<f64>() / runs.len() as f64).sqrt();
+                Ok(format!("{:.2} ± {:.2}", mean, deviation))
+            }).collect()
+        });
+        match timings {
+            Ok(timings) => {
+                let name = file.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                rows.push((name, timings));
+            },
+            Err(signal) => {
+                eprint!("{:?}: ", file);
+                status = report(signal);
+            },
+        }
+    }
+    println!("{:<20} {:>18} {:>18}", "script", "tree (ms)", "vm (ms)");
+    for (name, timings) in rows {
+        println!("{:<20} {:>18} {:>18}", name, timings[0], timings[1]);
+    }
+    status
+}
+
+/// Runs a program in a fresh interpreter and returns how long it took, in
+/// milliseconds. A quiet run does not print.
+fn time(exprs: &[Expr], engine: Engine, quiet: bool) -> Result<f64, Signal> {
+    let mut interpreter = Interpreter::with_engine(engine);
+    if quiet {
+        let print = Value::native("print", NativeFunction::Pure(|_| Ok(Value::Nil)));
+        interpreter.env.borrow_mut().variables.insert(Symbol::from("print"), print);
     }
+    let start = Instant::now();
+    interpreter.execute(exprs)?;
+    Ok(start.elapsed().as_secs_f64() * 1000.0)
 }
 
-fn evaluate(input: &str) {
-    let tokens: Vec<Token> = Lexer::tokenize(input);
-    let exprs: Vec<Expr> = vega::core::parser::Parser::parse(&mut tokens.into_iter().peekable());
+fn expand(input: &str, optimize: bool) -> ExitCode {
+    let exprs = match parse(input) {
+        Ok(exprs) => exprs,
+        Err(signal) => return report(signal),
+    };
     let mut interpreter = Interpreter::new();
-    interpreter.compute(&mut exprs.iter().peekable(), interpreter.env.clone());
+    match interpreter.expand_program(&exprs) {
+        Ok(mut expanded) => {
+            if optimize {
+                expanded = Optimizer::optimize(&expanded);
+            }
+            for expr in expanded {
+                println!("{}", expr.pretty());
+            }
+            ExitCode::SUCCESS
+        },
+        Err(signal) => report(signal),
+    }
 }
\ No newline at end of file
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{ENGINES, error_on, eval_on};
+use vega::core::error::ErrorKind;
+
+#[test]
+fn cond_picks_the_first_true_clause() {
+    let classify = r#"(fn classify (n) (cond ((< n 0) "negative") ((== n 0) "zero") ((< n 10) "small") (else "large")))"#;
+    for engine in ENGINES {
+        let classes = eval_on(engine, &format!("{} (list (classify -4) (classify 0) (classify 3) (classify 42))", classify));
+        assert_eq!(classes.to_string(), r#"("negative" "zero" "small" "large")"#);
+        assert_eq!(eval_on(engine, "(cond ((> 1 2) 1))").to_string(), "nil");
+        assert_eq!(error_on(engine, "(cond 5)").kind, ErrorKind::SyntaxError);
+    }
+}
+
+#[test]
+fn case_matches_numbers_and_key_lists() {
+    let day = r#"(fn day (d) (case d (1 "Mon") ((6 7) "Weekend") (else "Mid")))"#;
+    for engine in ENGINES {
+        assert_eq!(eval_on(engine, &format!("{} (list (day 1) (day 7) (day 4))", day)).to_string(), r#"("Mon" "Weekend" "Mid")"#);
+        assert_eq!(eval_on(engine, r#"(case 3 (1 "a"))"#).to_string(), "nil");
+    }
+}
+
+#[test]
+fn when_and_unless_run_every_body_form() {
+    for engine in ENGINES {
+        let hits = "(var hits 0) (when (> 3 2) (set hits (+ hits 1)) (set hits (+ hits 1))) (unless (> 3 2) (set hits 100)) hits";
+        assert_eq!(eval_on(engine, hits).to_string(), "2");
+        assert_eq!(eval_on(engine, "(list (when false 1) (unless true 1) (unless false 1 2))").to_string(), "(nil nil 2)");
+    }
+}
+
+#[test]
+fn if_has_an_optional_else_branch() {
+    for engine in ENGINES {
+        let branches = r#"(list (if (> 1 2) "bigger") (if (> 1 2) "bigger" "smaller") (if true 1))"#;
+        assert_eq!(eval_on(engine, branches).to_string(), r#"(nil "smaller" 1)"#);
+        assert_eq!(error_on(engine, "(if true)").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(if true 1 2 3)").kind, ErrorKind::SyntaxError);
+    }
+}
+
+#[test]
+fn case_matches_boolean_and_nil_keys() {
+    for engine in ENGINES {
+        assert_eq!(eval_on(engine, r#"(case true (true "yes") (false "no"))"#).to_string(), "yes");
+        assert_eq!(eval_on(engine, r#"(case false (true "yes") (false "no"))"#).to_string(), "no");
+        assert_eq!(eval_on(engine, r#"(case (> 1 2) ((nil false) "falsy") (else "truthy"))"#).to_string(), "falsy");
+        assert_eq!(eval_on(engine, r#"(case nil (false "false") (nil "nil"))"#).to_string(), "nil");
+    }
+}
+
+#[test]
+fn case_rejects_keys_that_are_not_literals() {
+    for engine in ENGINES {
+        assert_eq!(error_on(engine, r#"(var x 1) (case 1 (x "x"))"#).kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, r#"(case 1 ([1] "array"))"#).kind, ErrorKind::SyntaxError);
+    }
+}
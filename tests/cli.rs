@@ -0,0 +1,47 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Runs `vega <command>` on a script holding `source`.
+fn vega(command: &str, name: &str, source: &str) -> Output {
+    let path: PathBuf = std::env::temp_dir().join(format!("vega-cli-{}-{}.vega", std::process::id(), name));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_vega")).arg(command).arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn successful_scripts_exit_with_success() {
+    let output = vega("run", "ok", "(print (+ 1 2))");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3 \n");
+}
+
+#[test]
+fn unbalanced_brackets_are_reported() {
+    for command in ["run", "expand"] {
+        let output = vega(command, &format!("unclosed-{}", command), "(print (+ 1 2)");
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Unclosed"), "{:?}", output);
+        assert!(output.stdout.is_empty());
+    }
+}
+
+#[test]
+fn failing_scripts_exit_with_failure() {
+    let output = vega("run", "type-error", "(+ 1 \"a\")");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("TypeError"));
+    assert!(!vega("run", "throw", "(throw 1)").status.success());
+}
+
+#[test]
+fn expand_prints_the_program_without_macros() {
+    let output = vega("expand", "expand", "(defmacro twice (x) `(do ~x ~x))\n(twice (print \"hi\"))\n(var y (twice 1))");
+    assert!(output.status.success());
+    let expanded = String::from_utf8_lossy(&output.stdout);
+    assert!(expanded.contains("(do (print \"hi\") (print \"hi\"))"), "{}", expanded);
+    assert!(expanded.contains("(var y (do 1 1))"), "{}", expanded);
+    assert!(!expanded.contains("(twice"), "{}", expanded);
+}
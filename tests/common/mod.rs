@@ -0,0 +1,48 @@
+// Each test crate uses its own subset of the helpers.
+#![allow(dead_code)]
+
+use vega::core::error::Error;
+use vega::core::interpreter::Interpreter;
+use vega::core::signal::Signal;
+use vega::core::value::Value;
+use vega::core::vm::Engine;
+
+pub const ENGINES: [Engine; 2] = [Engine::Tree, Engine::Vm];
+
+/// Value of the last form of `source`, run by a fresh interpreter.
+pub fn eval_on(engine: Engine, source: &str) -> Value {
+    match Interpreter::with_engine(engine).eval_str(source) {
+        Ok(value) => value,
+        Err(signal) => panic!("expected {} to run, got {:?}", source, signal),
+    }
+}
+
+pub fn eval(source: &str) -> Value {
+    eval_on(Engine::Tree, source)
+}
+
+/// Error raised by `source`, run by a fresh interpreter.
+pub fn error_on(engine: Engine, source: &str) -> Error {
+    match Interpreter::with_engine(engine).eval_str(source) {
+        Err(Signal::Error(error)) => error,
+        other => panic!("expected {} to fail, got {:?}", source, other),
+    }
+}
+
+pub fn error(source: &str) -> Error {
+    error_on(Engine::Tree, source)
+}
+
+pub fn number(value: Value) -> f32 {
+    match value {
+        Value::Number(n) => n,
+        other => panic!("expected a number, got {:?}", other),
+    }
+}
+
+pub fn boolean(value: Value) -> bool {
+    match value {
+        Value::Bool(b) => b,
+        other => panic!("expected a bool, got {:?}", other),
+    }
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::{ENGINES, error_on, eval_on, number};
+use vega::core::error::ErrorKind;
+
+#[test]
+fn return_leaves_the_function_from_nested_forms() {
+    let find = "(fn find-first (arr target) (do (for (i) ((range 0 (len arr))) (if (== (unwrap (get arr i)) target) (return i) ())) -1))";
+    for engine in ENGINES {
+        assert_eq!(eval_on(engine, &format!("{} (list (find-first [4 8 15 16] 16) (find-first [4 8] 99))", find)).to_string(), "(3 -1)");
+        assert_eq!(number(eval_on(engine, "(fn f () (let ((x 1)) (if true (return x) ()) 2)) (f)")), 1.0);
+        assert_eq!(number(eval_on(engine, "(fn f () (do (match 1 (1 (return 3))) 4)) (f)")), 3.0);
+        assert_eq!(number(eval_on(engine, "(fn f () (while true (return 5))) (f)")), 5.0);
+    }
+}
+
+#[test]
+fn break_and_continue_steer_loops() {
+    let source = "(var i 0) (var total 0)
+        (var stopped (while (< i 100) (do
+            (set i (+ i 1))
+            (if (== (% i 2) 0) (continue) ())
+            (if (> i 9) (break i) ())
+            (set total (+ total i)))))
+        (list total stopped)";
+    for engine in ENGINES {
+        assert_eq!(eval_on(engine, source).to_string(), "(25 11)");
+        assert_eq!(number(eval_on(engine, "(var n 0) (for (i) ((range 0 10)) (do (if (> i 4) (break) ()) (set n (+ n 1)))) n")), 5.0);
+        assert_eq!(eval_on(engine, "(while true (break))").to_string(), "nil");
+        assert_eq!(number(eval_on(engine, "(fn f () (do (for (i) ((range 0 3)) (break)) 7)) (f)")), 7.0);
+    }
+}
+
+#[test]
+fn control_flow_outside_its_form_is_an_error() {
+    for engine in ENGINES {
+        assert_eq!(error_on(engine, "(break)").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(continue)").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(return 1)").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(while true (do (fn g () (break)) (g)))").kind, ErrorKind::SyntaxError);
+    }
+}
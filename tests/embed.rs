@@ -0,0 +1,131 @@
+mod common;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use common::number;
+use vega::core::error::{Error, ErrorKind};
+use vega::core::interpreter::Interpreter;
+use vega::core::signal::Signal;
+use vega::core::value::Value;
+use vega::core::vm::Engine;
+
+#[test]
+fn eval_str_returns_the_last_form() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(number(interpreter.eval_str("42").unwrap()), 42.0);
+    assert_eq!(number(interpreter.eval_str("(var x 5) (set x 6) x").unwrap()), 6.0);
+    assert!(matches!(interpreter.eval_str("").unwrap(), Value::Nil));
+}
+
+#[test]
+fn eval_str_keeps_globals_between_calls() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str("(var total 1)").unwrap();
+    interpreter.eval_str("(set total (+ total 2))").unwrap();
+    assert_eq!(number(interpreter.eval_str("total").unwrap()), 3.0);
+}
+
+#[test]
+fn eval_str_reports_syntax_errors() {
+    let mut interpreter = Interpreter::new();
+    match interpreter.eval_str("(+ 1 2") {
+        Err(Signal::Error(error)) => assert_eq!(error.kind, ErrorKind::SyntaxError),
+        other => panic!("expected a syntax error, got {:?}", other),
+    }
+}
+
+#[test]
+fn register_fn_closures_keep_their_state() {
+    let mut interpreter = Interpreter::new();
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    interpreter.register_fn("tick", move |_| {
+        counter.set(counter.get() + 1);
+        Ok(Value::Number(counter.get() as f32))
+    });
+    assert_eq!(number(interpreter.eval_str("(tick) (tick) (tick)").unwrap()), 3.0);
+    assert_eq!(calls.get(), 3);
+}
+
+#[test]
+fn register_fn_receives_arguments_and_reports_errors() {
+    let mut interpreter = Interpreter::new();
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let log = seen.clone();
+    interpreter.register_fn("log", move |args| match args {
+        [Value::String(line)] => {
+            log.borrow_mut().push(line.clone());
+            Ok(Value::Nil)
+        },
+        _ => Err(Error::type_error("log expects a string")),
+    });
+    interpreter.eval_str(r#"(log "a") (log (concat "b" "c"))"#).unwrap();
+    assert_eq!(*seen.borrow(), vec!["a".to_string(), "bc".to_string()]);
+    match interpreter.eval_str("(log 1)") {
+        Err(Signal::Error(error)) => assert_eq!(error.message, "log expects a string"),
+        other => panic!("expected an error, got {:?}", other),
+    }
+    let caught = interpreter.eval_str(r#"(try (log 1) (catch e (unwrap (get e "message"))))"#).unwrap();
+    assert!(matches!(caught, Value::String(message) if message == "log expects a string"));
+}
+
+#[test]
+fn globals_round_trip() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_global("limit", Value::Number(10.0));
+    assert_eq!(number(interpreter.get_global("limit").unwrap()), 10.0);
+    assert_eq!(number(interpreter.eval_str("(* limit 2)").unwrap()), 20.0);
+    interpreter.eval_str("(set limit 11)").unwrap();
+    assert_eq!(number(interpreter.get_global("limit").unwrap()), 11.0);
+    interpreter.set_global("limit", Value::String("none".to_string()));
+    assert!(matches!(interpreter.get_global("limit"), Some(Value::String(s)) if s == "none"));
+    assert!(interpreter.get_global("missing").is_none());
+    assert!(matches!(interpreter.get_global("len"), Some(Value::NativeFunction(_))));
+}
+
+#[test]
+fn call_function_calls_script_and_builtin_functions() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str("(fn add (a (b 10)) (+ a b))").unwrap();
+    assert_eq!(number(interpreter.call_function("add", vec![Value::Number(1.0), Value::Number(2.0)]).unwrap()), 3.0);
+    assert_eq!(number(interpreter.call_function("add", vec![Value::Number(1.0)]).unwrap()), 11.0);
+    assert_eq!(number(interpreter.call_function("max", vec![Value::Number(4.0), Value::Number(9.0)]).unwrap()), 9.0);
+    match interpreter.call_function("missing", Vec::new()) {
+        Err(Signal::Error(error)) => assert_eq!(error.kind, ErrorKind::UndefinedSymbol),
+        other => panic!("expected an undefined error, got {:?}", other),
+    }
+}
+
+#[test]
+fn call_function_calls_registered_closures() {
+    let mut interpreter = Interpreter::new();
+    let total = Rc::new(Cell::new(0.0));
+    let sum = total.clone();
+    interpreter.register_fn("add!", move |args| {
+        for arg in args {
+            if let Value::Number(n) = arg {
+                sum.set(sum.get() + n);
+            }
+        }
+        Ok(Value::Number(sum.get()))
+    });
+    interpreter.call_function("add!", vec![Value::Number(2.0), Value::Number(3.0)]).unwrap();
+    interpreter.eval_str("(add! 5)").unwrap();
+    assert_eq!(total.get(), 10.0);
+}
+
+#[test]
+fn embedding_works_under_the_vm() {
+    let mut interpreter = Interpreter::with_engine(Engine::Vm);
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    interpreter.register_fn("tick", move |_| {
+        counter.set(counter.get() + 1);
+        Ok(Value::Nil)
+    });
+    interpreter.set_global("rounds", Value::Number(4.0));
+    let source = "(fn run (n) (do (var i 0) (while (< i n) (do (tick) (set i (+ i 1)))) i)) (run rounds)";
+    assert_eq!(number(interpreter.eval_str(source).unwrap()), 4.0);
+    assert_eq!(number(interpreter.call_function("run", vec![Value::Number(2.0)]).unwrap()), 2.0);
+    assert_eq!(calls.get(), 6);
+}
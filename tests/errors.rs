@@ -0,0 +1,40 @@
+mod common;
+
+use common::{ENGINES, error_on};
+use vega::core::error::ErrorKind;
+use vega::core::interpreter::Interpreter;
+use vega::core::value::Value;
+
+#[test]
+fn malformed_forms_are_syntax_errors() {
+    for engine in ENGINES {
+        assert_eq!(error_on(engine, "(let)").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(let x x)").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(let (x) x)").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(let ((1 2)) 3)").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(let ((x 1 2)) x)").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(fn f () (:require))").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(fn f () (:test))").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(fn f (x) (:test (1)) x)").kind, ErrorKind::SyntaxError);
+    }
+}
+
+#[test]
+fn undefined_symbols_raise() {
+    for engine in ENGINES {
+        assert_eq!(error_on(engine, "undefined-thing").kind, ErrorKind::UndefinedSymbol);
+        assert_eq!(error_on(engine, "(+ 1 undefined-thing)").kind, ErrorKind::UndefinedSymbol);
+        assert_eq!(error_on(engine, "(fn f () undefined-thing) (f)").kind, ErrorKind::UndefinedSymbol);
+    }
+}
+
+#[test]
+fn undefined_symbols_can_be_caught() {
+    for engine in ENGINES {
+        let mut interpreter = Interpreter::with_engine(engine);
+        match interpreter.eval_str(r#"(try undefined-thing (catch e "caught"))"#) {
+            Ok(Value::String(caught)) => assert_eq!(caught, "caught"),
+            other => panic!("expected the error to be caught, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use common::{ENGINES, error_on, eval_on};
+use vega::core::error::ErrorKind;
+
+const LINE: &str = "(struct Point (x y)) (struct Line (from to)) (var l (Line (Point 0 0) (Point 3 4))) (fn double (n) (* n 2))";
+
+#[test]
+fn updates_leave_the_original_unchanged() {
+    for engine in ENGINES {
+        let source = "(struct Point (x y)) (var p (Point 1 2)) (var q (update p \"y\" + 10)) (list (unwrap (get p \"y\")) (unwrap (get q \"y\")))";
+        assert_eq!(eval_on(engine, source).to_string(), "(2 12)");
+        assert_eq!(eval_on(engine, "(var a [1 2 3]) (var b (assoc a 1 7)) (list a b)").to_string(), "([1 2 3] [1 7 3])");
+    }
+}
+
+#[test]
+fn nested_paths_go_through_objects_and_arrays() {
+    for engine in ENGINES {
+        let updated = format!("{} (var l2 (update-in l [\"to\" \"x\"] double)) (list (unwrap (get-in l2 [\"to\" \"x\"])) (unwrap (get-in l [\"to\" \"x\"])))", LINE);
+        assert_eq!(eval_on(engine, &updated).to_string(), "(6 3)");
+        let assoc = format!("{} (unwrap (get-in (assoc-in l [\"from\" \"y\"] 9) [\"from\" \"y\"]))", LINE);
+        assert_eq!(eval_on(engine, &assoc).to_string(), "9");
+        assert_eq!(eval_on(engine, "(update-in [[1 2] [3 4]] [1 0] + 100)").to_string(), "[[1 2] [103 4]]");
+    }
+}
+
+#[test]
+fn missing_keys_are_errors() {
+    for engine in ENGINES {
+        assert_eq!(eval_on(engine, "(err? (get-in [[1 2] [3 4]] [5 0]))").to_string(), "true");
+        assert_eq!(error_on(engine, "(struct Point (x y)) (assoc-in (Point 1 2) [\"z\"] 3)").kind, ErrorKind::UndefinedSymbol);
+        assert_eq!(error_on(engine, "(assoc [1 2 3] 5 0)").kind, ErrorKind::RuntimeError);
+    }
+}
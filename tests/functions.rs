@@ -0,0 +1,83 @@
+mod common;
+
+use common::{ENGINES, error, error_on, eval, eval_on};
+use vega::core::error::ErrorKind;
+use vega::core::expr::Expr;
+use vega::core::interpreter::Interpreter;
+use vega::core::params::{Param, Params};
+use vega::core::resolver::{Binding, Resolver};
+use vega::core::symbol::Symbol;
+use vega::core::value::Value;
+
+#[test]
+fn bare_natives_are_values() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(interpreter.eval_str("(typeof rand)").unwrap().to_string(), "Function");
+    assert!(matches!(interpreter.eval_str("(+ 1 (rand))"), Ok(Value::Number(n)) if (1.0..2.0).contains(&n)));
+    assert!(matches!(interpreter.eval_str("(apply rand [])"), Ok(Value::Number(n)) if (0.0..1.0).contains(&n)));
+    assert_eq!(interpreter.eval_str("((partial + 1) 2)").unwrap().to_string(), "3");
+}
+
+#[test]
+fn bare_natives_are_not_called() {
+    assert_eq!(error("(+ 1 rand)").kind, ErrorKind::TypeError);
+}
+
+#[test]
+fn parameter_slots_match_the_resolver() {
+    let names = ["a", "b", "c", "rest"].map(Symbol::from);
+    let params = Params {
+        positional: vec![
+            Param { name: names[0], default: None },
+            Param { name: names[1], default: Some(Expr::Number(2.0)) },
+            Param { name: names[2], default: Some(Expr::Symbol(names[1])) },
+        ],
+        rest: Some(names[3]),
+    };
+    let resolver = Resolver::function(&params);
+    for call in ["(f 1)", "(f 1 :c 3)", "(f 1 2 3 4 5)", "(f :c 3 :a 1)"] {
+        let source = format!("(fn f (a (b 2) (c b) & rest) (current-env)) {}", call);
+        let Value::Env(env) = eval(&source) else {
+            panic!("expected {} to return its scope", call);
+        };
+        for name in names {
+            let Binding::Local(address) = resolver.resolve(name) else {
+                panic!("expected {} to be a local", name);
+            };
+            assert!(env.borrow().variables.slot(address.slot, name).is_some(), "{} is not in slot {} after {}", name, address.slot, call);
+        }
+    }
+}
+
+#[test]
+fn empty_rest_parameter_prints_as_an_empty_array() {
+    assert_eq!(eval("(fn f (a & rest) rest) (f 1)").to_string(), "[]");
+    assert_eq!(eval("(fn f (a & rest) rest) (f 1 2 \"b\")").to_string(), "[2 b]");
+    assert_eq!(eval("[]").to_string(), "[]");
+}
+
+#[test]
+fn optional_rest_and_keyword_parameters() {
+    for engine in ENGINES {
+        let bound = eval_on(engine, "(fn f (a (b 2) & rest) (list a b rest)) (list (f 1) (f 1 3) (f 1 3 4 5) (f :b 7 :a 1))");
+        assert_eq!(bound.to_string(), "((1 2 []) (1 3 []) (1 3 [4 5]) (1 7 []))");
+        let defaults = eval_on(engine, "(fn f (a (b 2) (c (+ b 1))) (list a b c)) (list (f 0) (f 0 :c 9) (f 0 :b 5))");
+        assert_eq!(defaults.to_string(), "((0 2 3) (0 2 9) (0 5 6))");
+    }
+}
+
+#[test]
+fn calls_are_checked_against_the_parameters() {
+    for engine in ENGINES {
+        for call in ["(f 1)", "(f 1 2 3)", "(f 1 :c 2)", "(f 1 :a 2)"] {
+            assert_eq!(error_on(engine, &format!("(fn f (a b) a) {}", call)).kind, ErrorKind::TypeError, "{}", call);
+        }
+    }
+}
+
+#[test]
+fn malformed_parameter_lists_are_syntax_errors() {
+    for params in ["((a 1) b)", "(a & r s)", "(a a)"] {
+        assert_eq!(error(&format!("(fn f {} a)", params)).kind, ErrorKind::SyntaxError, "{}", params);
+    }
+}
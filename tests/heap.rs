@@ -0,0 +1,65 @@
+use vega::core::heap;
+use vega::core::interpreter::Interpreter;
+use vega::core::vm::Engine;
+
+// Cargo runs each test on its own thread, so each one sees its own heap.
+
+const COUNTERS: &str = r#"
+    (fn make-counter (start)
+      (do
+        (var count start)
+        (fn step () (do (set count (+ count 1)) count))
+        step))
+    (var i 0)
+    (var counter nil)
+    (while (< i 20000)
+      (do
+        (set counter (make-counter i))
+        (set i (+ i 1))))
+"#;
+
+fn closures_in_cycles_are_collected(engine: Engine) {
+    let mut interpreter = Interpreter::with_engine(engine);
+    interpreter.eval_str(COUNTERS).unwrap();
+    let stats = interpreter.memory();
+    assert_eq!(stats, heap::stats());
+    assert!(stats.collections > 0, "{:?}", stats);
+    assert!(stats.collected > 10000, "{:?}", stats);
+    assert!(stats.environments < 5000, "{:?}", stats);
+}
+
+#[test]
+fn closures_in_cycles_are_collected_by_the_tree_walker() {
+    closures_in_cycles_are_collected(Engine::Tree);
+}
+
+#[test]
+fn closures_in_cycles_are_collected_by_the_vm() {
+    closures_in_cycles_are_collected(Engine::Vm);
+}
+
+#[test]
+fn collect_garbage_frees_unreachable_cycles() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(COUNTERS).unwrap();
+    interpreter.collect_garbage();
+    let before = interpreter.memory();
+
+    // The last counter is only held by the global, dropping it leaves a cycle.
+    interpreter.eval_str("(set counter nil)").unwrap();
+    assert_eq!(interpreter.memory().environments, before.environments);
+    assert!(interpreter.collect_garbage() > 0);
+    let after = interpreter.memory();
+    assert!(after.environments < before.environments, "{:?} then {:?}", before, after);
+    assert_eq!(after.collections, before.collections + 1);
+    assert!(after.collected > before.collected);
+}
+
+#[test]
+fn live_closures_are_kept() {
+    let mut interpreter = Interpreter::new();
+    interpreter.eval_str(COUNTERS).unwrap();
+    interpreter.collect_garbage();
+    interpreter.eval_str("(counter)").unwrap();
+    assert_eq!(interpreter.eval_str("(counter)").unwrap().to_string(), "20001");
+}
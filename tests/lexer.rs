@@ -0,0 +1,48 @@
+use vega::core::expr::Span;
+use vega::core::interpreter::Interpreter;
+use vega::core::lexer::{Lexer, Token};
+use vega::core::parser::Parser;
+use vega::core::value::Value;
+
+fn run(source: &str) -> Value {
+    let tokens = Lexer::tokenize(source);
+    let exprs = Parser::parse(&mut tokens.into_iter().peekable());
+    Interpreter::new().execute(&exprs).expect("program should run")
+}
+
+#[test]
+fn number_at_end_of_input() {
+    let tokens = Lexer::tokenize("42");
+    assert_eq!(tokens.len(), 1);
+    assert!(matches!(tokens[0], (Token::Number(n), Span { line: 1, column: 1 }) if n == 42.0));
+}
+
+#[test]
+fn identifier_at_end_of_input() {
+    let tokens = Lexer::tokenize("(+ 1 2) x");
+    assert_eq!(tokens.len(), 6);
+    assert!(matches!(&tokens[5], (Token::Identifier(name), Span { line: 1, column: 9 }) if name == "x"));
+}
+
+#[test]
+fn atom_before_delimiter_is_unchanged() {
+    let tokens = Lexer::tokenize("x\n");
+    assert_eq!(tokens.len(), 1);
+    assert!(matches!(&tokens[0], (Token::Identifier(name), _) if name == "x"));
+}
+
+#[test]
+fn read_string_reads_a_lone_atom() {
+    assert!(matches!(run(r#"(unwrap (read-string "42"))"#), Value::Number(n) if n == 42.0));
+    assert!(matches!(run(r#"(unwrap (read-string "foo"))"#), Value::Symbol(name) if name == "foo"));
+}
+
+#[test]
+fn read_string_keeps_a_trailing_atom() {
+    assert_eq!(run(r#"(unwrap (read-string "(+ 1 2) x"))"#).to_string(), "(do (+ 1 2) x)");
+}
+
+#[test]
+fn program_ending_with_an_atom_returns_it() {
+    assert!(matches!(run("(var x 5) x"), Value::Number(n) if n == 5.0));
+}
@@ -0,0 +1,23 @@
+mod common;
+
+use common::{ENGINES, error_on, eval_on};
+use vega::core::error::ErrorKind;
+
+#[test]
+fn and_or_short_circuit() {
+    let hit = "(var hits 0) (fn hit (v) (do (set hits (+ hits 1)) v))";
+    for engine in ENGINES {
+        let evaluated = eval_on(engine, &format!("{} (list (and (hit false) (hit true)) (or (hit true) (hit false)) hits)", hit));
+        assert_eq!(evaluated.to_string(), "(false true 2)");
+        assert_eq!(eval_on(engine, "(or true undefined-name)").to_string(), "true");
+        assert_eq!(error_on(engine, "(and true undefined-name)").kind, ErrorKind::UndefinedSymbol);
+    }
+}
+
+#[test]
+fn and_or_return_the_deciding_operand() {
+    for engine in ENGINES {
+        let values = eval_on(engine, "(list (and) (or) (and 1 2 3) (or nil false 3) (and 1 nil 3) (or nil false))");
+        assert_eq!(values.to_string(), "(true false 3 3 nil false)");
+    }
+}
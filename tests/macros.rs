@@ -0,0 +1,64 @@
+mod common;
+
+use common::{ENGINES, error_on, eval, eval_on};
+use vega::core::error::ErrorKind;
+
+const SWAP: &str = "(defmacro swap-vars (a b) `(let ((tmp# ~a)) (set ~a ~b) (set ~b tmp#)))";
+
+#[test]
+fn quote_and_quasiquote_build_code() {
+    for engine in ENGINES {
+        let quoted = eval_on(engine, "(list 'x '(+ 1 2) (quote (a b)) (typeof 'x) (typeof '(a b)))");
+        assert_eq!(quoted.to_string(), r#"(x (+ 1 2) (a b) "Symbol" "List")"#);
+        assert_eq!(eval_on(engine, "(var n 5) `(n is ~n and ~@[1 2 3])").to_string(), "(n is 5 and 1 2 3)");
+        assert_eq!(eval_on(engine, "(eval '(+ 1 2))").to_string(), "3");
+        assert_eq!(error_on(engine, "~x").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(quote)").kind, ErrorKind::SyntaxError);
+    }
+}
+
+#[test]
+fn macros_expand_before_evaluation() {
+    for engine in ENGINES {
+        let unless_zero = "(defmacro unless-zero (value & body) `(if (== ~value 0) nil (do ~@body))) (list (unless-zero 0 (+ 1 \"a\")) (unless-zero 1 2))";
+        assert_eq!(eval_on(engine, unless_zero).to_string(), "(nil 2)");
+        let nested = "(defmacro my-unless (c & body) `(when (not ~c) ~@body)) (defmacro guard (c & body) `(my-unless ~c ~@body)) (guard false 9)";
+        assert_eq!(eval_on(engine, nested).to_string(), "9");
+        assert_eq!(error_on(engine, "(defmacro m (a) a) (m)").kind, ErrorKind::TypeError);
+    }
+}
+
+#[test]
+fn generated_names_do_not_capture_user_names() {
+    for engine in ENGINES {
+        assert_eq!(eval_on(engine, &format!("{} (var tmp 1) (var other 2) (swap-vars tmp other) (list tmp other)", SWAP)).to_string(), "(2 1)");
+        let my_or = "(defmacro my-or (a b) `(let ((value# ~a)) (if value# value# ~b))) (var value 7) (list (my-or false value) (my-or 3 value))";
+        assert_eq!(eval_on(engine, my_or).to_string(), "(7 3)");
+    }
+}
+
+#[test]
+fn each_expansion_gets_fresh_names() {
+    let expansions = eval(&format!("{} (list (macroexpand '(swap-vars a b)) (macroexpand '(swap-vars a b)))", SWAP));
+    let text = expansions.to_string();
+    assert!(text.contains("tmp__1") && text.contains("tmp__2"), "{}", text);
+}
+
+#[test]
+fn macroexpand_shows_one_or_every_step() {
+    let guard = "(defmacro my-unless (c & body) `(when (not ~c) ~@body)) (defmacro guard (c & body) `(my-unless ~c ~@body))";
+    for engine in ENGINES {
+        let steps = eval_on(engine, &format!("{} (list (macroexpand-1 '(guard ready (print \"go\"))) (macroexpand '(guard ready (print \"go\"))))", guard));
+        assert_eq!(steps.to_string(), r#"((my-unless ready (print "go")) (when (not ready) (print "go")))"#);
+        assert_eq!(eval_on(engine, "(macroexpand '(+ 1 2))").to_string(), "(+ 1 2)");
+    }
+}
+
+#[test]
+fn errors_in_expanded_code_point_to_the_macro_call() {
+    let source = "(defmacro unless-zero (value & body) `(if (== ~value 0) nil (do ~@body)))\n(unless-zero 1\n  (+ 1 \"a\"))";
+    for engine in ENGINES {
+        let location = error_on(engine, source).location.expect("expected a location");
+        assert_eq!((location.line, location.column), (2, 1));
+    }
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::{ENGINES, boolean, error, error_on, eval, eval_on, number};
+use vega::core::error::ErrorKind;
+
+#[test]
+fn division_by_zero_follows_ieee() {
+    assert_eq!(number(eval("(/ 1 0)")), f32::INFINITY);
+    assert_eq!(number(eval("(/ -1 0)")), f32::NEG_INFINITY);
+    assert!(number(eval("(/ 0 0)")).is_nan());
+    assert_eq!(number(eval("(quot 1 0)")), f32::INFINITY);
+    assert!(number(eval("(% 1 0)")).is_nan());
+}
+
+#[test]
+fn square_root_of_a_negative_number_is_nan() {
+    assert!(number(eval("(sqrt -1)")).is_nan());
+    assert_eq!(number(eval("(sqrt 9)")), 3.0);
+}
+
+#[test]
+fn integer_division_and_remainder() {
+    assert_eq!(number(eval("(quot 7 2)")), 3.0);
+    assert_eq!(number(eval("(quot -7 2)")), -3.0);
+    assert_eq!(number(eval("(% 7 2)")), 1.0);
+    assert_eq!(number(eval("(% -7 2)")), -1.0);
+    assert_eq!(number(eval("(^ 2 10)")), 1024.0);
+}
+
+#[test]
+fn nan_and_infinite_predicates() {
+    assert!(boolean(eval("(nan? (sqrt -1))")));
+    assert!(boolean(eval("(nan? (/ 0 0))")));
+    assert!(!boolean(eval("(nan? 1)")));
+    assert!(!boolean(eval("(nan? (/ 1 0))")));
+    assert!(boolean(eval("(infinite? (/ 1 0))")));
+    assert!(boolean(eval("(infinite? (/ -1 0))")));
+    assert!(!boolean(eval("(infinite? 1)")));
+    assert!(!boolean(eval("(infinite? (sqrt -1))")));
+    assert_eq!(error("(nan? \"a\")").kind, ErrorKind::TypeError);
+}
+
+#[test]
+fn bitwise_operators_take_integers() {
+    assert_eq!(number(eval("(bit-and 6 3)")), 2.0);
+    assert_eq!(number(eval("(bit-or 6 3)")), 7.0);
+    assert_eq!(number(eval("(bit-xor 6 3)")), 5.0);
+    assert_eq!(number(eval("(bit-not 0)")), -1.0);
+    assert_eq!(number(eval("(bit-shl 1 3)")), 8.0);
+    assert_eq!(number(eval("(bit-shr 8 3)")), 1.0);
+}
+
+#[test]
+fn bitwise_operators_reject_fractions() {
+    for source in ["(bit-and 1.5 1)", "(bit-or 1 2.5)", "(bit-xor 1 0.1)", "(bit-not 0.5)", "(bit-shl 1.5 1)", "(bit-shr 4 0.5)"] {
+        assert_eq!(error(source).kind, ErrorKind::TypeError, "{}", source);
+    }
+    assert_eq!(error("(bit-and (/ 1 0) 1)").kind, ErrorKind::TypeError);
+}
+
+#[test]
+fn min_and_max_propagate_nan() {
+    assert_eq!(number(eval("(min 3 1 2)")), 1.0);
+    assert_eq!(number(eval("(max 1 5 2)")), 5.0);
+    assert!(number(eval("(min 1 nan 3)")).is_nan());
+    assert!(number(eval("(max nan 2)")).is_nan());
+    assert!(number(eval("(max 2 nan)")).is_nan());
+    assert!(number(eval("(clamp nan 0 1)")).is_nan());
+    assert_eq!(number(eval("(clamp 5 0 1)")), 1.0);
+}
+
+#[test]
+fn arithmetic_is_variadic() {
+    for engine in ENGINES {
+        let results = eval_on(engine, "(list (+ 1 2 3) (- 10 1 2) (- 5) (* 2 3 4) (/ 24 2 3) (/ 2) (+ 7) (* 3) (+))");
+        assert_eq!(results.to_string(), "(6 7 -5 24 4 0.5 7 3 0)");
+        assert_eq!(error_on(engine, "(-)").kind, ErrorKind::TypeError);
+        assert_eq!(error_on(engine, "(+ 1 \"a\")").kind, ErrorKind::TypeError);
+    }
+}
+
+#[test]
+fn comparisons_chain() {
+    for engine in ENGINES {
+        let results = eval_on(engine, "(list (< 1 2 3) (< 1 3 2) (<= 1 1 2) (> 3 2 1) (>= 3 3 4) (< 1) (== 1 1 1) (== 1 1 2) (== \"a\" \"a\" \"a\") (!= 1 2))");
+        assert_eq!(results.to_string(), "(true false true true false true true false true true)");
+        assert_eq!(error_on(engine, "(<)").kind, ErrorKind::TypeError);
+        assert_eq!(error_on(engine, "(< 1 \"a\")").kind, ErrorKind::TypeError);
+        assert_eq!(error_on(engine, "(== 1 \"a\")").kind, ErrorKind::TypeError);
+        assert_eq!(error_on(engine, "(!= 1 2 3)").kind, ErrorKind::TypeError);
+    }
+}
@@ -0,0 +1,88 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use vega::core::expr::Expr;
+use vega::core::interpreter::Interpreter;
+use vega::core::lexer::Lexer;
+use vega::core::optimizer::Optimizer;
+use vega::core::parser::Parser;
+use vega::core::signal::Signal;
+use vega::core::value::Value;
+use vega::core::vm::Engine;
+
+const PROGRAMS: [&str; 8] = [
+    r#"(fn area (r) (* pi (* r r)))
+       (emit (area (+ 1 1)) (* 60 (* 60 24)) (-> 0.42 (* 100) floor))
+       (emit (len (concat "vega" " lang")) (if (> 2 1) "folded" (emit "never")))"#,
+    r#"(emit (match (+ 1 1) (1 "one") (2 "two") (3 "three")))
+       (emit (match 4 (1 "one") (2 "two")))
+       (emit (cond ((> 1 2) "no") ((< 1 2) "yes")))"#,
+    r#"(let ((width 4) (height (* 2 3)) (label "area"))
+           (emit label (* width height)))
+       (let ((x 1))
+           (do (set x (+ x 1)) (emit x)))"#,
+    r#"(fn abs (x) "shadowed")
+       (emit (abs -1))
+       (var nil 3)
+       (emit (if nil "rebound" "falsy"))"#,
+    r#"(emit (/ 1 0) (- 0 (/ 1 0)) (sqrt -1) (nan? (sqrt -1)) (infinite? (/ -1 0)))"#,
+    r#"(var total 0)
+       (for (i) ((range 0 (* 2 5))) (set total (+ total (* i 2))))
+       (emit total [1 (+ 1 1) (* 3 1)])"#,
+    r#"(emit (try (+ 1 "a") (catch e (unwrap (get e "kind")))))
+       (emit (if (== 1 1) (+ 1 2)))"#,
+    r#"(fn inc (x) (+ x (- 3 2)))
+       (emit (inc 1) (apply inc [2]) (concat (typeof (+ 1 2)) "!"))
+       (+ 40 2)"#,
+];
+
+fn parse(source: &str) -> Vec<Expr> {
+    let tokens = Lexer::tokenize(source);
+    Parser::check(&tokens).unwrap();
+    Parser::parse(&mut tokens.into_iter().peekable())
+}
+
+/// What the program emits, then its result.
+fn run(program: &[Expr], engine: Engine) -> Vec<String> {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let lines = log.clone();
+    let mut interpreter = Interpreter::with_engine(engine);
+    interpreter.register_fn("emit", move |args| {
+        lines.borrow_mut().push(args.iter().map(Value::to_string).collect::<Vec<_>>().join(" "));
+        Ok(Value::Nil)
+    });
+    let outcome = match interpreter.execute(program) {
+        Ok(value) => format!("=> {}", value),
+        Err(Signal::Error(error)) => format!("error {:?}", error.kind),
+        Err(other) => format!("signal {:?}", other),
+    };
+    log.borrow_mut().push(outcome);
+    log.take()
+}
+
+#[test]
+fn optimized_programs_behave_the_same() {
+    for source in PROGRAMS {
+        let program = parse(source);
+        let optimized = Optimizer::optimize(&program);
+        for engine in [Engine::Tree, Engine::Vm] {
+            assert_eq!(run(&optimized, engine), run(&program, engine), "optimizing {}", source);
+        }
+    }
+}
+
+#[test]
+fn optimized_programs_read_back_the_same() {
+    for source in PROGRAMS {
+        let program = parse(source);
+        let printed: Vec<String> = Optimizer::optimize(&program).iter().map(Expr::pretty).collect();
+        let reread = parse(&printed.join("\n"));
+        assert_eq!(run(&reread, Engine::Tree), run(&program, Engine::Tree), "reading back {}", printed.join("\n"));
+    }
+}
+
+#[test]
+fn optimizer_folds_constants() {
+    let program = parse("(emit (* 60 (* 60 24)) (if (> 2 1) \"folded\" \"never\"))");
+    let printed: Vec<String> = Optimizer::optimize(&program).iter().map(Expr::pretty).collect();
+    assert_eq!(printed, ["(emit 86400 \"folded\")"]);
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::{ENGINES, error_on, eval_on, number};
+use vega::core::error::ErrorKind;
+
+const SHOW: &str = r#"
+    (protocol Show (show (self)))
+    (struct Point (x y))
+    (impl Point Show (fn show (self) (concat "P" (unwrap (get self "x")))))
+    (impl Number Show (fn show (self) (concat "n" self)))
+    (impl String Show (fn show (self) (concat "'" self "'")))
+    (impl Array Show (fn show (self) "array"))
+"#;
+
+#[test]
+fn protocol_methods_dispatch_on_the_runtime_type() {
+    for engine in ENGINES {
+        let shown = eval_on(engine, &format!("{} (list (show (Point 1 2)) (show 42) (show \"v\") (show [1]) (. (Point 3 4) show))", SHOW));
+        assert_eq!(shown.to_string(), r#"("P1" "n42" "'v'" "array" "P3")"#);
+    }
+}
+
+#[test]
+fn protocol_methods_take_extra_arguments() {
+    let source = "(protocol Compare (compare (self other))) (impl Number Compare (fn compare (self other) (- self other))) (compare 5 3)";
+    for engine in ENGINES {
+        assert_eq!(number(eval_on(engine, source)), 2.0);
+    }
+}
+
+#[test]
+fn missing_implementations_name_the_protocol_and_type() {
+    for engine in ENGINES {
+        let missing = error_on(engine, &format!("{} (struct Line (from to)) (show (Line 1 2))", SHOW));
+        assert_eq!(missing.kind, ErrorKind::TypeError);
+        assert!(missing.message.contains("Show") && missing.message.contains("Line"), "{}", missing.message);
+        let builtin = error_on(engine, &format!("{} (show true)", SHOW));
+        assert!(builtin.message.contains("Show") && builtin.message.contains("Bool"), "{}", builtin.message);
+    }
+}
+
+#[test]
+fn implementations_are_checked_against_the_protocol() {
+    for engine in ENGINES {
+        assert_eq!(error_on(engine, "(protocol Show (show (self))) (impl Number Show (fn other (self) 1))").kind, ErrorKind::UndefinedSymbol);
+        assert_eq!(error_on(engine, "(protocol Show (show (self))) (impl Widget Show (fn show (self) 1))").kind, ErrorKind::UndefinedSymbol);
+    }
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use std::rc::Rc;
+
+use common::{ENGINES, error_on, eval_on, number};
+use vega::core::error::ErrorKind;
+use vega::core::value::Value;
+
+#[test]
+fn references_are_shared_between_variables() {
+    for engine in ENGINES {
+        assert_eq!(number(eval_on(engine, "(var counter (ref 0)) (var alias counter) (swap! alias + 5) (swap! counter + 1) (deref counter)")), 6.0);
+        assert_eq!(number(eval_on(engine, "(var c (ref 1)) (fn bump (r) (swap! r + 1)) (bump c) (bump c) (deref c)")), 3.0);
+        assert_eq!(eval_on(engine, "(var c (ref 1)) (reset! c 42) (list (deref c) c)").to_string(), "(42 ref(42))");
+        assert_eq!(number(eval_on(engine, "(var c (ref 1)) (swap! c + 1)")), 2.0);
+    }
+}
+
+#[test]
+fn dereferencing_other_values_is_an_error() {
+    for engine in ENGINES {
+        assert_eq!(error_on(engine, "(deref 5)").kind, ErrorKind::TypeError);
+        assert_eq!(error_on(engine, "(swap! 5 +)").kind, ErrorKind::TypeError);
+    }
+}
+
+#[test]
+fn arrays_are_shared_until_updated() {
+    for engine in ENGINES {
+        let Value::List(values) = eval_on(engine, "(var a (range 0 1000)) (var b a) (var c (assoc b 0 9)) (list a b c)") else {
+            panic!("expected a list");
+        };
+        let [Value::Array(a), Value::Array(b), Value::Array(c)] = &values[..] else {
+            panic!("expected three arrays");
+        };
+        assert!(Rc::ptr_eq(a, b));
+        assert!(!Rc::ptr_eq(a, c));
+        assert_eq!((a[0].to_string(), c[0].to_string()), ("0".to_string(), "9".to_string()));
+    }
+}
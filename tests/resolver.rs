@@ -0,0 +1,57 @@
+mod common;
+
+use common::{ENGINES, eval_on};
+use vega::core::params::{Param, Params};
+use vega::core::resolver::{Address, Binding, Resolver};
+use vega::core::symbol::Symbol;
+
+#[test]
+fn names_resolve_to_the_innermost_scope() {
+    let [a, b, c] = ["a", "b", "c"].map(Symbol::from);
+    let params = Params { positional: vec![Param { name: a, default: None }], rest: Some(b) };
+    let mut resolver = Resolver::function(&params);
+    assert_eq!(resolver.resolve(a), Binding::Local(Address { depth: 0, slot: 0 }));
+    assert_eq!(resolver.resolve(b), Binding::Local(Address { depth: 0, slot: 1 }));
+    resolver.push();
+    resolver.declare(c);
+    resolver.declare(a);
+    resolver.declare(c);
+    assert_eq!(resolver.resolve(c), Binding::Local(Address { depth: 0, slot: 0 }));
+    assert_eq!(resolver.resolve(a), Binding::Local(Address { depth: 0, slot: 1 }));
+    assert_eq!(resolver.resolve(b), Binding::Local(Address { depth: 1, slot: 1 }));
+    assert_eq!(resolver.resolve(Symbol::from("free")), Binding::Free { outer: 2 });
+    resolver.pop();
+    assert_eq!(resolver.resolve(c), Binding::Free { outer: 1 });
+}
+
+#[test]
+fn globals_are_declared_nowhere() {
+    let mut resolver = Resolver::default();
+    resolver.declare(Symbol::from("x"));
+    assert_eq!(resolver.resolve(Symbol::from("x")), Binding::Free { outer: 0 });
+}
+
+#[test]
+fn resolved_locals_read_the_right_scope() {
+    let cases = [
+        ("(var x 1) (fn f (n) (do (var y 2) (let ((x 5)) (+ x y n)))) (f 100)", "107"),
+        ("(fn outer (a) (do (fn inner (b) (do (fn innermost (c) (+ a b c)) (innermost 3))) (inner 2))) (outer 1)", "6"),
+        ("(fn f (n) (do (var acc 0) (for (i) ((range 0 n)) (set acc (+ acc i))) acc)) (f 10)", "45"),
+        ("(fn f (a) (let ((a 2)) a)) (f 1)", "2"),
+        ("(var x 1) (fn f () (do (set x 5) x)) (list (f) x)", "(5 5)"),
+        ("(fn f () later) (var later 3) (f)", "3"),
+    ];
+    for engine in ENGINES {
+        for (source, expected) in cases {
+            assert_eq!(eval_on(engine, source).to_string(), expected, "{}", source);
+        }
+    }
+}
+
+#[test]
+fn names_bound_at_runtime_shadow_resolved_ones() {
+    for engine in ENGINES {
+        assert_eq!(eval_on(engine, "(var x 1) (fn f () (do (eval '(var x 10)) x)) (list (f) x)").to_string(), "(10 1)");
+        assert_eq!(eval_on(engine, "(fn f (x) (do (fn g () (do (eval '(var x 10)) x)) (g))) (f 1)").to_string(), "10");
+    }
+}
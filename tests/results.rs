@@ -0,0 +1,35 @@
+mod common;
+
+use common::{ENGINES, error_on, eval_on};
+use vega::core::error::ErrorKind;
+
+#[test]
+fn result_helpers() {
+    for engine in ENGINES {
+        let helpers = "(list (ok 1) (err \"x\") (ok? (ok 1)) (err? (err 1)) (unwrap (ok 2)) (unwrap-or (err 1) 7))";
+        assert_eq!(eval_on(engine, helpers).to_string(), "(ok(1) err(x) true true 2 7)");
+        assert_eq!(eval_on(engine, "(map-err (parse \"x\") (do (fn f (m) \"bad\") f))").to_string(), "err(bad)");
+        assert_eq!(error_on(engine, "(unwrap (err \"boom\"))").kind, ErrorKind::RuntimeError);
+        assert_eq!(error_on(engine, "(unwrap 5)").kind, ErrorKind::TypeError);
+    }
+}
+
+#[test]
+fn question_mark_returns_errors_early() {
+    let parse_pair = "(fn parse-pair (a b) (do (var x (? (parse a))) (var y (? (parse b))) (ok (+ x y))))";
+    for engine in ENGINES {
+        let pairs = eval_on(engine, &format!("{} (list (parse-pair \"1\" \"2\") (parse-pair \"1\" \"two\"))", parse_pair));
+        assert_eq!(pairs.to_string(), "(ok(3) err(Cannot parse \"two\" as a number))");
+        assert_eq!(error_on(engine, "(fn f () (? 5)) (f)").kind, ErrorKind::TypeError);
+    }
+}
+
+#[test]
+fn fallible_natives_return_results() {
+    for engine in ENGINES {
+        let results = eval_on(engine, "(list (parse \" 42 \") (get [1 2] 0) (get [1 2] 5) (get \"ab\" 1))");
+        assert_eq!(results.to_string(), "(ok(42) ok(1) err(Index 5 out of bounds for array of length 2) ok(b))");
+        assert_eq!(eval_on(engine, "(struct P (x)) (list (get (P 1) \"x\") (get (P 1) \"y\"))").to_string(), "(ok(1) err(No field y in P))");
+        assert_eq!(error_on(engine, "(struct P (x)) (get (P 1) 5)").kind, ErrorKind::TypeError);
+    }
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use std::rc::Rc;
+
+use common::{ENGINES, eval_on};
+use vega::core::expr::Expr;
+use vega::core::value::Value;
+use vega::core::vm::Engine;
+
+/// The two values of a `(list a b)` program.
+fn pair(engine: Engine, source: &str) -> (Value, Value) {
+    let Value::List(values) = eval_on(engine, source) else {
+        panic!("expected {} to return a list", source);
+    };
+    match &values[..] {
+        [a, b] => (a.clone(), b.clone()),
+        other => panic!("expected two values, got {:?}", other),
+    }
+}
+
+#[test]
+fn copies_of_a_function_share_its_parts() {
+    for engine in ENGINES {
+        let (Value::Function { params: p1, body: b1, chunk: c1, .. }, Value::Function { params: p2, body: b2, chunk: c2, .. }) =
+            pair(engine, "(fn f (x) (+ x 1)) (var g f) (g 1) (list f g)") else {
+            panic!("expected two functions");
+        };
+        assert!(Rc::ptr_eq(&p1, &p2));
+        assert!(Rc::ptr_eq(&b1, &b2));
+        assert!(Rc::ptr_eq(&c1, &c2));
+    }
+}
+
+#[test]
+fn closures_from_one_form_share_its_body() {
+    for engine in ENGINES {
+        let (Value::Function { body: b1, .. }, Value::Function { body: b2, .. }) =
+            pair(engine, "(fn adder (n) (do (fn add (x) (+ x n)) add)) (list (adder 1) (adder 2))") else {
+            panic!("expected two functions");
+        };
+        let (Expr::List(items1, _), Expr::List(items2, _)) = (b1.as_ref(), b2.as_ref()) else {
+            panic!("expected call bodies");
+        };
+        assert!(Rc::ptr_eq(items1, items2));
+    }
+}
+
+#[test]
+fn arrays_are_passed_without_copies() {
+    for engine in ENGINES {
+        let (Value::Array(a), Value::Array(b)) = pair(engine, "(fn id (a) a) (var big (range 0 1000)) (list big (id big))") else {
+            panic!("expected two arrays");
+        };
+        assert!(Rc::ptr_eq(&a, &b));
+    }
+}
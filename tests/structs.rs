@@ -0,0 +1,70 @@
+mod common;
+
+use common::{ENGINES, error, error_on, eval, eval_on, number};
+use vega::core::error::ErrorKind;
+
+#[test]
+fn constructors_take_positional_and_keyword_fields() {
+    let point = "(struct Point (x (y 0)))";
+    assert_eq!(eval(&format!("{} (unwrap (get (Point 1 2) \"y\"))", point)).to_string(), "2");
+    assert_eq!(eval(&format!("{} (unwrap (get (Point :y 3 :x 1) \"y\"))", point)).to_string(), "3");
+    assert_eq!(eval(&format!("{} (unwrap (get (Point 1) \"y\"))", point)).to_string(), "0");
+    assert_eq!(eval(&format!("{} (var p 5) (fn twice (a) (* a 2)) (unwrap (get (Point p (twice 2)) \"y\"))", point)).to_string(), "4");
+}
+
+#[test]
+fn constructors_validate_fields() {
+    assert_eq!(error("(struct Point (x y)) (Point 1)").kind, ErrorKind::TypeError);
+    assert_eq!(error("(struct Point (x y)) (Point 1 2 3)").kind, ErrorKind::TypeError);
+    assert_eq!(error("(struct Point (x y)) (Point :z 1)").kind, ErrorKind::UndefinedSymbol);
+}
+
+#[test]
+fn old_binding_form_asks_for_migration() {
+    let migration = error("(struct Point (x y)) (Point p (1 2))");
+    assert_eq!(migration.kind, ErrorKind::SyntaxError);
+    assert!(migration.message.contains("(var p (Point ...))"), "{}", migration.message);
+}
+
+#[test]
+fn bound_names_before_a_list_are_field_values() {
+    let point = "(struct Point (x y))";
+    for engine in ENGINES {
+        assert_eq!(eval_on(engine, &format!("{} (var x 1) (unwrap (get (Point x (\"a\")) \"y\"))", point)).to_string(), "a");
+        assert_eq!(eval_on(engine, &format!("{} (var n 2) (unwrap (get (Point n (5)) \"y\"))", point)).to_string(), "5");
+    }
+}
+
+const POINT_METHODS: &str = r#"
+    (struct Point (x y))
+    (impl Point
+        (fn norm2 (self) (+ (* (unwrap (get self "x")) (unwrap (get self "x"))) (* (unwrap (get self "y")) (unwrap (get self "y")))))
+        (fn scale (self k) (Point (* k (unwrap (get self "x"))) (* k (unwrap (get self "y"))))))
+"#;
+
+#[test]
+fn methods_dispatch_on_the_object_class() {
+    for engine in ENGINES {
+        assert_eq!(number(eval_on(engine, &format!("{} (. (Point 3 4) norm2)", POINT_METHODS))), 25.0);
+        assert_eq!(number(eval_on(engine, &format!("{} (Point/norm2 (Point 3 4))", POINT_METHODS))), 25.0);
+        assert_eq!(number(eval_on(engine, &format!("{} (. (. (Point 1 2) scale 3) norm2)", POINT_METHODS))), 45.0);
+    }
+}
+
+#[test]
+fn impl_blocks_add_to_the_class() {
+    let source = "(struct Point (x y)) (impl Point (fn f (self) 1)) (impl Point (fn g (self) 2)) (+ (. (Point 1 2) f) (. (Point 1 2) g))";
+    assert_eq!(number(eval(source)), 3.0);
+}
+
+#[test]
+fn unknown_methods_and_classes_are_errors() {
+    for engine in ENGINES {
+        let missing = error_on(engine, &format!("{} (. (Point 3 4) missing)", POINT_METHODS));
+        assert_eq!(missing.kind, ErrorKind::UndefinedSymbol);
+        assert!(missing.message.contains("missing"), "{}", missing.message);
+        assert_eq!(error_on(engine, &format!("{} (Point/missing (Point 3 4))", POINT_METHODS)).kind, ErrorKind::UndefinedSymbol);
+        assert_eq!(error_on(engine, "(. 5 norm2)").kind, ErrorKind::TypeError);
+        assert_eq!(error_on(engine, "(impl Nope (fn f (self) 1))").kind, ErrorKind::UndefinedSymbol);
+    }
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{ENGINES, eval, eval_on};
+use vega::core::interpreter::Interpreter;
+use vega::core::symbol::{self, Symbol};
+use vega::core::value::Value;
+
+#[test]
+fn gensyms_do_not_grow_the_interner() {
+    for engine in ENGINES {
+        let mut interpreter = Interpreter::with_engine(engine);
+        let source = r#"
+            (defmacro my-or (a b) `(let ((value# ~a)) (if value# value# ~b)))
+            (for (i) ((range 0 500)) (gensym "tmp") (my-or false i))
+        "#;
+        interpreter.eval_str(source).unwrap();
+        let before = Symbol::interned_count();
+        interpreter.eval_str(source).unwrap();
+        assert_eq!(Symbol::interned_count(), before);
+    }
+}
+
+#[test]
+fn gensyms_print_with_their_generation() {
+    let Value::Symbol(symbol) = eval(r#"(gensym "tmp")"#) else {
+        panic!("expected a symbol");
+    };
+    assert!(symbol.is_gensym());
+    assert_eq!(symbol.to_string(), "tmp__1");
+    assert_eq!(symbol.as_str(), "tmp");
+}
+
+#[test]
+fn gensyms_differ_from_symbols_with_the_same_name() {
+    for engine in ENGINES {
+        let Value::List(symbols) = eval_on(engine, r#"(list (gensym "tmp") (symbol "tmp__1") (symbol "tmp"))"#) else {
+            panic!("expected a list");
+        };
+        let [Value::Symbol(gensym), Value::Symbol(read_back), Value::Symbol(prefix)] = &symbols[..] else {
+            panic!("expected three symbols");
+        };
+        assert_eq!(gensym.to_string(), read_back.to_string());
+        assert_ne!(gensym, read_back);
+        assert_ne!(gensym, prefix);
+    }
+}
+
+#[test]
+fn equal_names_share_one_symbol() {
+    assert_eq!(Symbol::from("point"), Symbol::from("point".to_string()));
+    assert_ne!(Symbol::from("point"), Symbol::from("Point"));
+    assert_eq!(Symbol::from("if"), symbol::IF);
+    assert_eq!(Symbol::from("x"), "x");
+    assert_eq!(Symbol::from("x").as_str(), "x");
+}
+
+#[test]
+fn keywords_start_with_a_colon() {
+    assert!(Symbol::from(":name").is_keyword());
+    assert!(!Symbol::from(":").is_keyword());
+    assert!(!Symbol::from("name").is_keyword());
+}
+
+#[test]
+fn symbols_read_from_source_are_interned() {
+    let mut interpreter = Interpreter::new();
+    let before = Symbol::interned_count();
+    interpreter.eval_str("(var fresh-name 1) fresh-name").unwrap();
+    let after = Symbol::interned_count();
+    assert_eq!(after, before + 1);
+    interpreter.eval_str("(set fresh-name 2) fresh-name").unwrap();
+    assert_eq!(Symbol::interned_count(), after);
+}
@@ -0,0 +1,21 @@
+mod common;
+
+use common::{ENGINES, error_on, eval_on};
+use vega::core::error::ErrorKind;
+
+#[test]
+fn threading_inserts_the_value_first_or_last() {
+    for engine in ENGINES {
+        let threaded = eval_on(engine, "(fn inc (n) (+ n 1)) (list (-> 5 inc (* 2) (- 1)) (->> 5 inc (* 2) (- 1)) (->> [1 2 3] (len)) (-> 5))");
+        assert_eq!(threaded.to_string(), "(11 -11 3 5)");
+    }
+}
+
+#[test]
+fn as_threading_names_the_value() {
+    for engine in ENGINES {
+        assert_eq!(eval_on(engine, "(as-> 10 x (+ x 1) (* 2 x) (- 100 x))").to_string(), "78");
+        assert_eq!(error_on(engine, "(as-> 5 (+ 1 2))").kind, ErrorKind::SyntaxError);
+        assert_eq!(error_on(engine, "(->)").kind, ErrorKind::SyntaxError);
+    }
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use common::{eval_on, number};
+use vega::core::vm::Engine;
+
+#[test]
+fn functions_defined_by_eval_get_their_own_chunk() {
+    // Bodies freed between iterations may be allocated at the same address.
+    let source = r#"
+        (var total 0)
+        (for (i) ((range 0 500))
+            (set total (+ total (eval (list 'do (list 'fn 'tmp '(a) (list '+ 'a i)) '(tmp 1))))))
+        total
+    "#;
+    let expected: f32 = (0..500).map(|i| (1 + i) as f32).sum();
+    assert_eq!(number(eval_on(Engine::Vm, source)), expected);
+}
+
+#[test]
+fn closures_made_by_the_same_form_keep_their_scope() {
+    let source = r#"
+        (fn adder (n) (do (fn add (x) (+ x n)) add))
+        (var add2 (adder 2))
+        (var add5 (adder 5))
+        (+ (add2 1) (add5 1))
+    "#;
+    assert_eq!(number(eval_on(Engine::Vm, source)), 9.0);
+}